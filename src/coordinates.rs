@@ -0,0 +1,87 @@
+use std::fmt;
+
+use crate::gtp::COLUMNS;
+use crate::sgf;
+
+/**
+ * How the rows and columns along the edges of the board are labelled
+ * Gtp letters the columns from A, skipping I, and numbers the rows from the bottom, as in D4.
+ * Sgf letters both from a at the top left, as SGF records write points.
+ * Numeric numbers both from 1 at the top left.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CoordinateStyle {
+    #[default]
+    Gtp,
+    Sgf,
+    Numeric,
+}
+
+impl CoordinateStyle {
+    /**
+     * The style after this one, wrapping around to Gtp
+     */
+    pub fn next(self) -> Self {
+        match self {
+            CoordinateStyle::Gtp => CoordinateStyle::Sgf,
+            CoordinateStyle::Sgf => CoordinateStyle::Numeric,
+            CoordinateStyle::Numeric => CoordinateStyle::Gtp,
+        }
+    }
+
+    /**
+     * Label for column col, counted from 0 at the left
+     */
+    pub fn column_label(self, col: usize) -> String {
+        match self {
+            CoordinateStyle::Gtp => (COLUMNS[col] as char).to_string(),
+            CoordinateStyle::Sgf => sgf::point_letter(col).to_string(),
+            CoordinateStyle::Numeric => (col + 1).to_string(),
+        }
+    }
+
+    /**
+     * Label for row, counted from 0 at the top of a board with height rows
+     */
+    pub fn row_label(self, row: usize, height: usize) -> String {
+        match self {
+            CoordinateStyle::Gtp => (height - row).to_string(),
+            CoordinateStyle::Sgf => sgf::point_letter(row).to_string(),
+            CoordinateStyle::Numeric => (row + 1).to_string(),
+        }
+    }
+}
+
+impl fmt::Display for CoordinateStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoordinateStyle::Gtp => write!(f, "GTP"),
+            CoordinateStyle::Sgf => write!(f, "SGF"),
+            CoordinateStyle::Numeric => write!(f, "Numeric"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coordinates::CoordinateStyle;
+    use crate::game::Move;
+    use crate::gtp::vertex_text;
+    use crate::sgf::point_text;
+
+    #[test]
+    fn test_labels_match_move_text() {
+        // Labels read the same way as the moves written by GTP and SGF
+        let (row, col) = (3, 15);
+        let gtp = CoordinateStyle::Gtp;
+        let label = gtp.column_label(col) + &gtp.row_label(row, 19);
+        assert_eq!(label, vertex_text(Move::Play(row, col), 19));
+        let sgf = CoordinateStyle::Sgf;
+        assert_eq!(sgf.column_label(col) + &sgf.row_label(row, 19), point_text(row, col));
+
+        assert_eq!(gtp.column_label(8), "J");
+        assert_eq!(CoordinateStyle::Numeric.row_label(0, 9), "1");
+        assert_eq!(CoordinateStyle::Numeric.column_label(24), "25");
+        assert_eq!(CoordinateStyle::default().next().next().next(), CoordinateStyle::Gtp);
+    }
+}
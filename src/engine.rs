@@ -0,0 +1,392 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::game::{Color, GameState, Move};
+use crate::gtp::{parse_vertex, vertex_text};
+use crate::tree::GameTree;
+
+//Playing against an external engine that speaks GTP, such as GNU Go or KataGo, run as a subprocess
+
+/**
+ * Reason the engine could not give a move
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineError {
+    NoCommand,
+    Start(String),
+    Timeout(String),
+    Exited,
+    Failure { command: String, message: String },
+    BadResponse(String),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::NoCommand => write!(f, "No engine command was given"),
+            EngineError::Start(error) => write!(f, "Could not start the engine: {error}"),
+            EngineError::Timeout(command) => write!(f, "The engine did not answer '{command}' in time"),
+            EngineError::Exited => write!(f, "The engine has exited"),
+            EngineError::Failure { command, message } => write!(f, "The engine refused '{command}': {message}"),
+            EngineError::BadResponse(response) => write!(f, "Could not read the engine's answer '{response}'"),
+        }
+    }
+}
+
+/**
+ * The game as the engine needs it: the starting stones and the moves played since
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineGame {
+    pub width: usize,
+    pub height: usize,
+    pub komi: f32,
+    pub stones: Vec<(usize, usize, Color)>,
+    pub moves: Vec<(Color, Move)>,
+    pub to_move: Color,
+}
+
+impl EngineGame {
+    /**
+     * The game at the tree's current node
+     * GTP has no way to take stones off, so after setup stones in the middle of the game
+     * the engine is given the current position without its moves
+     */
+    pub fn from_tree(tree: &GameTree) -> Self {
+        let state = tree.state();
+        let mut moves = Vec::new();
+        for node_id in tree.path(tree.current()) {
+            let node = tree.node(node_id);
+            if !node.setup.is_empty() {
+                return EngineGame::from_position(state);
+            }
+            moves.extend(node.played);
+        }
+        EngineGame {
+            stones: tree.start().board().stones(),
+            moves,
+            ..EngineGame::from_position(state)
+        }
+    }
+
+    fn from_position(state: &GameState) -> Self {
+        let board = state.board();
+        EngineGame {
+            width: board.width,
+            height: board.height,
+            komi: board.rules.komi,
+            stones: board.stones(),
+            moves: Vec::new(),
+            to_move: state.to_move(),
+        }
+    }
+}
+
+/**
+ * A running GTP engine
+ * A thread reads the engine's output so every command can wait with a timeout.
+ * After a timeout the engine is killed, since a late answer would be taken for the next one.
+ */
+pub struct GtpEngine {
+    child: Child,
+    stdin: ChildStdin,
+    responses: Receiver<String>,
+    timeout: Duration,
+}
+
+impl GtpEngine {
+    /**
+     * Start the engine from a command line like `gnugo --mode gtp` and check that it answers
+     */
+    pub fn start(command_line: &str, timeout: Duration) -> Result<Self, EngineError> {
+        let words = split_command_line(command_line);
+        let (program, args) = words.split_first().ok_or(EngineError::NoCommand)?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|error| EngineError::Start(error.to_string()))?;
+        let stdin = child.stdin.take().ok_or(EngineError::Exited)?;
+        let stdout = child.stdout.take().ok_or(EngineError::Exited)?;
+
+        // A response is every line up to the next empty one, the channel closes when the engine exits
+        let (sender, responses) = mpsc::channel();
+        thread::spawn(move || {
+            let mut response = String::new();
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => return,
+                };
+                let line = line.trim_end_matches('\r');
+                if !line.trim().is_empty() {
+                    if !response.is_empty() {
+                        response.push('\n');
+                    }
+                    response.push_str(line);
+                } else if !response.is_empty() && sender.send(std::mem::take(&mut response)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let mut engine = GtpEngine { child, stdin, responses, timeout };
+        engine.send("protocol_version")?;
+        Ok(engine)
+    }
+
+    /**
+     * Send one command and wait for the answer, without its leading = and id
+     */
+    pub fn send(&mut self, command: &str) -> Result<String, EngineError> {
+        if writeln!(self.stdin, "{}", command).and_then(|_| self.stdin.flush()).is_err() {
+            return Err(EngineError::Exited);
+        }
+        match self.responses.recv_timeout(self.timeout) {
+            Ok(response) => {
+                let mut chars = response.chars();
+                let status = chars.next();
+                let text = chars.as_str().trim_start_matches(|character: char| character.is_ascii_digit()).trim().to_string();
+                match status {
+                    Some('=') => Ok(text),
+                    Some('?') => Err(EngineError::Failure { command: command.to_string(), message: text }),
+                    _ => Err(EngineError::BadResponse(response)),
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let _ = self.child.kill();
+                Err(EngineError::Timeout(command.to_string()))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(EngineError::Exited),
+        }
+    }
+
+    /**
+     * Give the engine the whole game, starting from an empty board
+     * GTP only has square boards, a rectangular one is sent with the
+     * rectangular_boardsize extension that KataGo and some other engines understand
+     */
+    pub fn sync(&mut self, game: &EngineGame) -> Result<(), EngineError> {
+        if game.width == game.height {
+            self.send(&format!("boardsize {}", game.width))?;
+        } else {
+            self.send(&format!("rectangular_boardsize {} {}", game.width, game.height))?;
+        }
+        self.send("clear_board")?;
+        self.send(&format!("komi {}", game.komi))?;
+        let stones = game.stones.iter().map(|&(row, col, color)| (color, Move::Play(row, col)));
+        for (player_color, played) in stones.chain(game.moves.iter().copied()) {
+            if played != Move::Resign {
+                self.send(&format!("play {} {}", color_text(player_color), vertex_text(played, game.height)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Ask the engine for a move for player_color on a board of width columns and height rows
+     */
+    pub fn genmove(&mut self, player_color: Color, width: usize, height: usize) -> Result<Move, EngineError> {
+        let response = self.send(&format!("genmove {}", color_text(player_color)))?;
+        if response.eq_ignore_ascii_case("resign") {
+            return Ok(Move::Resign);
+        }
+        parse_vertex(&response, width, height).map_err(|_| EngineError::BadResponse(response))
+    }
+}
+
+impl Drop for GtpEngine {
+    //Ask the engine to quit, and make sure it is gone if it does not within a second
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "quit").and_then(|_| self.stdin.flush());
+        for _ in 0..20 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/**
+ * Runs the engine on its own thread so the GUI keeps drawing while it thinks
+ * Each request sends the whole game and asks for the next move. The engine is started on the
+ * first request, and started again after an error.
+ */
+pub struct EngineWorker {
+    requests: Sender<EngineGame>,
+    replies: Mutex<Receiver<Result<Move, EngineError>>>,
+}
+
+impl EngineWorker {
+    pub fn spawn(command_line: String, timeout: Duration) -> Self {
+        let (requests, request_receiver) = mpsc::channel::<EngineGame>();
+        let (reply_sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            let mut engine: Option<GtpEngine> = None;
+            for game in request_receiver {
+                let reply = match engine.take() {
+                    Some(running) => Ok(running),
+                    None => GtpEngine::start(&command_line, timeout),
+                }.and_then(|mut running| {
+                    running.sync(&game)?;
+                    let next_move = running.genmove(game.to_move, game.width, game.height)?;
+                    engine = Some(running);
+                    Ok(next_move)
+                });
+                if let Err(error) = &reply {
+                    eprintln!("Engine error: {error}");
+                }
+                if reply_sender.send(reply).is_err() {
+                    return;
+                }
+            }
+        });
+        EngineWorker {
+            requests,
+            replies: Mutex::new(replies),
+        }
+    }
+
+    /**
+     * Ask for a move in the given game, the answer comes back through try_reply
+     */
+    pub fn request(&self, game: EngineGame) {
+        let _ = self.requests.send(game);
+    }
+
+    /**
+     * The answer to the oldest request, if the engine has finished it
+     */
+    pub fn try_reply(&self) -> Option<Result<Move, EngineError>> {
+        self.replies.lock().ok()?.try_recv().ok()
+    }
+}
+
+fn color_text(player_color: Color) -> &'static str {
+    match player_color {
+        Color::Black => "b",
+        Color::White => "w",
+    }
+}
+
+/**
+ * Split a command line into words, keeping anything in double quotes together
+ */
+pub fn split_command_line(command_line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut in_word = false;
+    for character in command_line.chars() {
+        match character {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            _ if character.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            _ => {
+                word.push(character);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::engine::{split_command_line, EngineError, EngineGame, EngineWorker, GtpEngine};
+    use crate::game::{Color, GameState, Move};
+    use crate::tree::GameTree;
+
+    fn mock_engine(mode: &str) -> String {
+        format!("sh \"{}/tests/mock_gtp_engine.sh\" {}", env!("CARGO_MANIFEST_DIR"), mode)
+    }
+
+    #[test]
+    fn test_split_command_line() {
+        assert_eq!(split_command_line("gnugo --mode gtp"), vec!["gnugo", "--mode", "gtp"]);
+        assert_eq!(
+            split_command_line("  katago gtp -model \"my models/b18.bin.gz\" \"\""),
+            vec!["katago", "gtp", "-model", "my models/b18.bin.gz", ""]
+        );
+        assert!(split_command_line("   ").is_empty());
+    }
+
+    #[test]
+    fn test_engine_replays_game_and_moves() {
+        let mut state = GameState::new(9);
+        state.place_fixed_handicap(2);
+        let mut tree = GameTree::new(state);
+        tree.play(Move::Play(4, 4)).unwrap();
+        tree.play(Move::Pass).unwrap();
+        let game = EngineGame::from_tree(&tree);
+        assert_eq!(game.moves, vec![(Color::White, Move::Play(4, 4)), (Color::Black, Move::Pass)]);
+        assert_eq!(game.stones.len(), 2);
+        assert_eq!(game.to_move, Color::White);
+
+        let mut engine = GtpEngine::start(&mock_engine("C3"), Duration::from_secs(5)).unwrap();
+        engine.sync(&game).unwrap();
+        assert_eq!(engine.genmove(Color::White, 9, 9), Ok(Move::Play(6, 2)));
+        assert_eq!(engine.send("showboard"), Ok("G7 C3 E5 pass".to_string()));
+        assert_eq!(
+            engine.send("frobnicate"),
+            Err(EngineError::Failure { command: "frobnicate".to_string(), message: "unknown command".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_engine_errors() {
+        let timeout = Duration::from_millis(300);
+        assert!(matches!(GtpEngine::start("no-such-gtp-engine", timeout), Err(EngineError::Start(_))));
+        assert_eq!(GtpEngine::start("", timeout).err(), Some(EngineError::NoCommand));
+
+        let mut engine = GtpEngine::start(&mock_engine("slow"), timeout).unwrap();
+        assert_eq!(engine.genmove(Color::Black, 9, 9), Err(EngineError::Timeout("genmove b".to_string())));
+        let mut engine = GtpEngine::start(&mock_engine("crash"), timeout).unwrap();
+        assert_eq!(engine.genmove(Color::Black, 9, 9), Err(EngineError::Exited));
+        let mut engine = GtpEngine::start(&mock_engine("Z99"), timeout).unwrap();
+        assert_eq!(engine.genmove(Color::Black, 9, 9), Err(EngineError::BadResponse("Z99".to_string())));
+        let mut engine = GtpEngine::start(&mock_engine("resign"), timeout).unwrap();
+        assert_eq!(engine.genmove(Color::Black, 9, 9), Ok(Move::Resign));
+    }
+
+    #[test]
+    fn test_worker_restarts_after_error() {
+        let worker = EngineWorker::spawn(mock_engine("crash"), Duration::from_secs(5));
+        let game = EngineGame::from_tree(&GameTree::new(GameState::new(9)));
+        let wait = || {
+            let started = Instant::now();
+            loop {
+                if let Some(reply) = worker.try_reply() {
+                    return reply;
+                }
+                assert!(started.elapsed() < Duration::from_secs(10));
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+        worker.request(game.clone());
+        assert_eq!(wait(), Err(EngineError::Exited));
+        worker.request(game);
+        assert_eq!(wait(), Err(EngineError::Exited));
+    }
+}
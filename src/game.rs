@@ -1,19 +1,168 @@
 use std::{
-    collections::HashMap, fmt,
+    collections::{HashMap, HashSet}, fmt,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
-use bevy::prelude::Component;
+use crate::scoring;
+use crate::zobrist;
 
-pub const EMPTY: u8 = 0;
-pub const WHITE: u8 = 1;
-pub const BLACK: u8 = 2;
-pub const WHITE_TERR: u8 = 3;
-pub const BLACK_TERR: u8 = 4;
+/**
+ * The color of a player and of their stones
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Color {
+    Black,
+    White,
+}
+
+impl Color {
+    pub fn opponent(self) -> Color {
+        match self {
+            Color::Black => Color::White,
+            Color::White => Color::Black,
+        }
+    }
+
+    /**
+     * B or W, the letter SGF, GTP and game results use for the color
+     */
+    pub fn letter(self) -> &'static str {
+        match self {
+            Color::Black => "B",
+            Color::White => "W",
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Color::Black => write!(f, "Black"),
+            Color::White => write!(f, "White"),
+        }
+    }
+}
+
+/**
+ * What is on one intersection of the board, either nothing or a stone
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PointState {
+    #[default]
+    Empty,
+    Stone(Color),
+}
+
+impl PointState {
+    /**
+     * The color of the stone on the point, None if it is empty
+     */
+    pub fn stone(self) -> Option<Color> {
+        match self {
+            PointState::Empty => None,
+            PointState::Stone(color) => Some(color),
+        }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self == PointState::Empty
+    }
+}
+
+impl From<Color> for PointState {
+    fn from(color: Color) -> Self {
+        PointState::Stone(color)
+    }
+}
+
+/**
+ * Who an intersection counts for once the game is scored
+ * Territory is only worked out for a score, it is never stored in the board.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TerritoryOwner {
+    Black,
+    White,
+    //Points that count for neither player, living stones and dame
+    #[default]
+    Neutral,
+}
+
+impl From<Color> for TerritoryOwner {
+    fn from(color: Color) -> Self {
+        match color {
+            Color::Black => TerritoryOwner::Black,
+            Color::White => TerritoryOwner::White,
+        }
+    }
+}
+
+//Largest board side supported, limited by the Zobrist key table
+pub const MAX_BOARD_SIZE: usize = 25;
+//Smallest board side, a single line has no room for a game
+pub const MIN_BOARD_SIZE: usize = 2;
+
+//Komi in a handicap game under every rule set, only enough to break ties
+pub const HANDICAP_KOMI: f32 = 0.5;
+
+//Whether moves, captures and undos are logged to stderr, see set_move_log
+static MOVE_LOG: AtomicBool = AtomicBool::new(false);
+
+/**
+ * Turn the per-move logging on stderr on or off
+ * It is off by default so GTP sessions and other users of the library get no output they did not ask for.
+ */
+pub fn set_move_log(enabled: bool) {
+    MOVE_LOG.store(enabled, Ordering::Relaxed);
+}
+
+macro_rules! move_log {
+    ($($arg:tt)*) => {
+        if MOVE_LOG.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/**
+ * Check that the player owning player_model may place a stone at (row,col)
+ * Runs every check place_stone does without changing the board.
+ */
+pub fn check_move(
+    board: &Board,
+    player_model: &PlayerModel,
+    opponent_model: &PlayerModel,
+    row: usize,
+    col: usize,
+) -> Result<(), MoveError> {
+    if board.phase != GamePhase::Playing {
+        return Err(MoveError::GameOver);
+    }
+    if !board.is_on_board(row, col) {
+        return Err(MoveError::OutOfBounds);
+    }
+    if player_model.player.player_color != board.to_move() {
+        return Err(MoveError::WrongTurn);
+    }
+    if !board.point_state(row, col).is_empty() {
+        return Err(MoveError::Occupied);
+    }
+    if !board.rules.suicide_allowed && is_suicide(board, player_model, opponent_model, row, col) {
+        return Err(MoveError::Suicide);
+    }
+    if let Some(violation) = check_ko(board, row, col) {
+        return Err(MoveError::Ko(violation));
+    }
+    Ok(())
+}
 
 /**
  * Place a stone on the board and run through the accompanying logic
  * This includes checking to see if the opponent has had any chains captured.
- * Returns true if stone has been placed, false if it has not
+ * If the rule set allows suicide, a stone that leaves its own chain without liberties
+ * takes that chain off the board and the opponent gets the stones as prisoners.
+ * Returns the captured stones and the chain the new stone belongs to,
+ * or the reason the stone could not be placed
  */
 pub fn place_stone(
     board: &mut Board,
@@ -21,65 +170,234 @@ pub fn place_stone(
     opponent_model: &mut PlayerModel,
     row: usize,
     col: usize,
-) -> bool {
-    if (board.is_white_turn && player_model.player.player_color != WHITE) ||
-       (!board.is_white_turn && player_model.player.player_color != BLACK) {
+) -> Result<MoveOutcome, MoveError> {
+    if let Err(error) = check_move(board, player_model, opponent_model, row, col) {
+        move_log!("Rejecting move at {:?}/{:?}: {}", row, col, error);
+        return Err(error);
+    }
+
+    let player_color = player_model.player.player_color;
+    let opponent_color = player_model.player.opponent_color;
+    move_log!("Placing {:?} at location {:?}/{:?}", player_color, row, col);
+    board.update_board_color(row, col, player_color.into());
+    //The new stone takes a liberty from every chain next to it
+    update_adjacent_liberties(board, player_model, opponent_model, row, col, false);
+    let friends = get_adjacent(board, row, col, player_color.into());
+    let chain_id = update_chain(board, player_model, friends, row, col);
+    //For the opponent
+    let mut captures = Vec::new();
+    for (opponent_row, opponent_col) in get_adjacent(board, row, col, opponent_color.into()) {
+        let opponent_chain_id = board.chain_id_at(opponent_row, opponent_col);
+        if opponent_model.get_liberties(opponent_chain_id).is_some_and(HashSet::is_empty) {
+            move_log!("Chain id {opponent_chain_id} has no liberties. It has been eliminated.");
+            captures.extend(remove_chain(board, opponent_model, player_model, opponent_chain_id));
+        }
+    }
+    update_prisoners(board, player_color, captures.len());
+    let mut suicide = Vec::new();
+    if player_model.get_liberties(chain_id).is_some_and(HashSet::is_empty) {
+        //The player's own chain is left without liberties and is removed instead
+        move_log!("Suicide at {:?}/{:?} removes chain id {chain_id}", row, col);
+        suicide = remove_chain(board, player_model, opponent_model, chain_id);
+        update_prisoners(board, opponent_color, suicide.len());
+    }
+    let new_friend_chain = player_model.player_chains.get(&chain_id).cloned().unwrap_or_default();
+    board.ko_point = find_ko_point(board, row, col, &captures);
+    board.consecutive_passes = 0;
+    board.toggle_turn();
+    board.position_history.push((board.hash, board.is_white_turn));
+    Ok(MoveOutcome {
+        captured: captures,
+        chain: new_friend_chain,
+        suicide,
+    })
+}
+
+/**
+ * The player to move passes instead of placing a stone
+ * A pass lifts any ko restriction. Two passes in a row end play and move the game to scoring.
+ * With pass stones the passing player hands the opponent a prisoner, and play only
+ * ends on two passes in a row when white passed last.
+ * Returns false if the game is no longer being played
+ */
+pub fn pass(board: &mut Board) -> bool {
+    if board.phase != GamePhase::Playing {
         return false;
     }
+    move_log!("{} passes", board.to_move());
+    if board.rules.pass_stones {
+        if board.is_white_turn {
+            board.black_captured += 1;
+        } else {
+            board.white_captured += 1;
+        }
+    }
+    let white_passed = board.is_white_turn;
+    // A GTP engine asked to move after the game keeps passing
+    board.consecutive_passes = board.consecutive_passes.saturating_add(1);
+    board.ko_point = None;
+    board.toggle_turn();
+    if board.consecutive_passes >= 2 && (white_passed || !board.rules.pass_stones) {
+        board.phase = GamePhase::Scoring;
+    }
+    true
+}
+
+/**
+ * The player with player_color resigns, which ends the game immediately
+ * A player may resign at any time, not only on their own turn
+ * Returns false if the game is already finished
+ */
+pub fn resign(board: &mut Board, player_color: Color) -> bool {
+    if board.phase == GamePhase::Finished {
+        return false;
+    }
+    board.result = Some(GameResult::Resignation { winner: player_color.opponent() });
+    board.phase = GamePhase::Finished;
+    true
+}
+
+/**
+ * Star points (hoshi) marked on a board of width columns and height rows, as (row, col)
+ * Boards with both sides at least 7 have the corner points on the third line, or the fourth
+ * from 13 up, and tengen when both sides are odd. Odd sides of 15 or more also get a point
+ * halfway along, which gives the usual five points on 9x9 and 13x13 and nine on 19x19.
+ */
+pub fn star_points(width: usize, height: usize) -> Vec<(usize, usize)> {
+    if width.min(height) < 7 {
+        return Vec::new();
+    }
+    // The lines the star points sit on along one side, the middle one only on long odd sides
+    let lines = |side: usize| {
+        let near = if side >= 13 { 3 } else { 2 };
+        let middle = (side % 2 == 1 && side >= 15).then_some(side / 2);
+        (near, middle, side - 1 - near)
+    };
+    let (top, middle_row, bottom) = lines(height);
+    let (left, middle_col, right) = lines(width);
+    let mut points = vec![(top, left), (top, right), (bottom, left), (bottom, right)];
+    if let Some(col) = middle_col {
+        points.extend([(top, col), (bottom, col)]);
+    }
+    if let Some(row) = middle_row {
+        points.extend([(row, left), (row, right)]);
+    }
+    if width % 2 == 1 && height % 2 == 1 {
+        points.push((height / 2, width / 2));
+    }
+    points
+}
 
-    if board.board_state[row][col].player_color == 0 {
-        if check_for_self_capture(board, row, col) {
+/**
+ * Standard star points for a fixed handicap of 2 to 9 stones, as (row, col) with row 0 at the top
+ * Stones go on the 3-3 points on 9x9 and on the 4-4 points on 13x13 and 19x19,
+ * in the same order as GTP fixed_handicap.
+ * Returns None for other board sizes or stone counts
+ */
+pub fn handicap_points(board_size: usize, stones: usize) -> Option<Vec<(usize, usize)>> {
+    let edge = match board_size {
+        9 => 2,
+        13 | 19 => 3,
+        _ => return None,
+    };
+    if !(2..=9).contains(&stones) {
+        return None;
+    }
+    let low = edge;
+    let high = board_size - 1 - edge;
+    let middle = board_size / 2;
+
+    // Lower left, upper right, upper left, lower right
+    let mut points = vec![(high, low), (low, high), (low, low), (high, high)];
+    points.truncate(stones.min(4));
+    if stones >= 6 {
+        // Left and right sides
+        points.push((middle, low));
+        points.push((middle, high));
+    }
+    if stones >= 8 {
+        // Bottom and top sides
+        points.push((high, middle));
+        points.push((low, middle));
+    }
+    if stones >= 5 && stones % 2 == 1 {
+        // Odd counts from five up add tengen
+        points.push((middle, middle));
+    }
+    Some(points)
+}
+
+/**
+ * Place fixed handicap stones for black on the standard star points, see handicap_points
+ * Returns false if the handicap is not available on this board or the game has already started
+ */
+pub fn place_fixed_handicap(
+    board: &mut Board,
+    black_model: &mut PlayerModel,
+    white_model: &mut PlayerModel,
+    stones: usize,
+) -> bool {
+    // Fixed handicap is only defined for the standard square boards
+    let points = if board.is_square() { handicap_points(board.width, stones) } else { None };
+    match points {
+        Some(points) => place_handicap(board, black_model, white_model, &points),
+        None => false,
+    }
+}
+
+/**
+ * Place handicap stones for black at the given points (free placement)
+ * Only allowed before the first move. Afterwards white moves next.
+ * Returns false if any point is off the board, repeated or not playable, if the stones would
+ * leave themselves without liberties, or if the game has already started. Nothing changes then.
+ */
+pub fn place_handicap(
+    board: &mut Board,
+    black_model: &mut PlayerModel,
+    white_model: &mut PlayerModel,
+    points: &[(usize, usize)],
+) -> bool {
+    if board.has_started() || board.handicap > 0 || points.len() < 2 {
+        return false;
+    }
+    for (index, &(row, col)) in points.iter().enumerate() {
+        if !board.is_on_board(row, col) || points[..index].contains(&(row, col)) {
             return false;
         }
-        println!("Placing {:?} at location {:?}/{:?}", player_model.player.player_color, row, col);
-        board.update_board_color(row, col, player_model.player.player_color);
-        let friends = get_adjacent(&mut board.board_state, board.board_size, row, col, player_model.player.player_color);
-        let new_friend_chain = update_chain(&mut board.board_state, player_model, friends, row, col);
-        player_model.add_player_chain(&Board::generate_id(row, col), new_friend_chain);
-        //For the opponent
-        let opponent_liberties = update_player_liberties(&mut board.board_state, board.board_size, opponent_model);
-        opponent_model.set_player_liberties(opponent_liberties);
-        let removed_chain_keys = check_for_conquered(opponent_model, &mut board.board_state);
-        cleanup_captured(opponent_model, removed_chain_keys);
-        board.toggle_turn();
-        true
-    } else {
-        false
     }
+
+    // Place the stones on a copy so a refused point leaves the game as it was
+    let (mut trial, mut black, mut white) = (board.clone(), black_model.clone(), white_model.clone());
+    for &(row, col) in points {
+        trial.is_white_turn = false;
+        match place_stone(&mut trial, &mut black, &mut white, row, col) {
+            // Where suicide is allowed, the last stone could otherwise take black's own stones off
+            Ok(outcome) if outcome.suicide.is_empty() => {}
+            _ => return false,
+        }
+    }
+    trial.is_white_turn = true;
+    trial.handicap = points.len();
+    // The handicap setup is the starting position of the game
+    trial.position_history = vec![(trial.hash, trial.is_white_turn)];
+    *board = trial;
+    *black_model = black;
+    *white_model = white;
+    true
 }
 
 //Find surrounding adjacent neighbors of a color
 //Only borrows
 pub fn get_adjacent(
-    board_state: &mut [Vec<Intersection>],
-    board_size: usize,
+    board: &Board,
     row: usize,
     col: usize,
-    desired_color: u8,
+    desired_state: PointState,
 ) -> Vec<(usize, usize)> {
     //Check left, right, above and below for "friendly" intersections
-    let mut friends: Vec<(usize, usize)> = Vec::new();
-    let intersection: &Intersection = &board_state[row][col];
-    //Check left friend
-    if intersection.col != 0 && board_state[intersection.row][intersection.col - 1].player_color == desired_color {
-        friends.push((intersection.row, intersection.col-1));
-    }
-
-    //Check right friend
-    if intersection.col + 1 < board_size && board_state[intersection.row][intersection.col + 1].player_color == desired_color {
-        friends.push((intersection.row, intersection.col+1));
-    }
-
-    //Check friend above
-    if intersection.row != 0 && board_state[intersection.row - 1][intersection.col].player_color == desired_color {
-        friends.push((intersection.row - 1, intersection.col));
-    }
-
-    //Check friend below
-    if intersection.row + 1 < board_size && board_state[intersection.row + 1][intersection.col].player_color == desired_color {
-        friends.push((intersection.row + 1, intersection.col));
-    }
-    friends
+    board.adjacent_points(row, col)
+        .filter(|&(adjacent_row, adjacent_col)| board.point_state(adjacent_row, adjacent_col) == desired_state)
+        .collect()
 }
 
 
@@ -87,344 +405,1157 @@ pub fn get_adjacent(
      * Call after placing a stone
      * Create a new chain based on the friends that were found around the newly placed stone
      * row and col represent the location of the newly placed stone
+     * When chains merge, the stones of the smaller chain are relabelled with the id of the larger one.
+     * Returns the id of the chain the new stone belongs to
     **/
     pub fn update_chain(
-        board_state: &mut [Vec<Intersection>],
+        board: &mut Board,
         player_model: &mut PlayerModel,
         friends: Vec<(usize, usize)>,
         row: usize,
         col: usize,
-    ) -> Vec<(usize, usize)> {
-        //All of these friends needs to be made into a single chain
-        //They will have the id of the newly placed stone
-        let mut new_friend_chain: Vec<(usize, usize)> = vec![(row, col)];
+    ) -> usize {
+        let stone_liberties = get_adjacent(board, row, col, PointState::Empty).into_iter().collect();
+        let mut chain_id = board.chain_id_at(row, col);
+        player_model.add_player_chain(chain_id, vec![(row, col)], stone_liberties);
         for friend in friends {
-            let friend_chain_id: String = board_state[friend.0][friend.1].chain_id.clone();
-            let friend_chain: Option<(String, Vec<(usize, usize)>)> = player_model.remove_player_chain(&friend_chain_id);
-            player_model.remove_player_liberties(&friend_chain_id);
-            if let Some((old_chain, vec)) = friend_chain {
-                println!("Merging old chain {:?} with new chain id{:?}", old_chain, &friend_chain_id);
-                for (friend_row, friend_col) in vec {
-                    //All of the friends of this chain will now belong to the same chain with the id of the new stone
-                    board_state[friend_row][friend_col].chain_id = board_state[row][col].chain_id.clone();
-                    new_friend_chain.push((friend_row, friend_col));
+            let friend_chain_id = board.chain_id_at(friend.0, friend.1);
+            if friend_chain_id == chain_id {
+                continue;
+            }
+            let (kept_id, merged_id) = if player_model.chain_len(friend_chain_id) >= player_model.chain_len(chain_id) {
+                (friend_chain_id, chain_id)
+            } else {
+                (chain_id, friend_chain_id)
+            };
+            if let Some((stones, liberties)) = player_model.remove_player_chain(merged_id) {
+                move_log!("Merging old chain {:?} into chain id {:?}", merged_id, kept_id);
+                for &(stone_row, stone_col) in &stones {
+                    board.set_chain_id(stone_row, stone_col, kept_id);
                 }
+                player_model.add_player_chain(kept_id, stones, liberties);
             }
+            chain_id = kept_id;
         }
-        new_friend_chain
+        chain_id
     }
 
     /**
-     * Check for liberties for all pieces potentially impacted by a stone placement
-     * For each chain, get all associated liberties. If a chain doesn't have any liberties, it has been conquered.
-     * This is done on a specific player's chains
+     * Call after a stone has been placed on or taken off (row,col)
+     * Every chain next to that point loses (placed) or gains (removed) it as a liberty.
+     * Only those chains are touched. The chain of a placed stone itself is handled by update_chain
      */
-    pub fn update_player_liberties(
-        board_state: &mut [Vec<Intersection>],
-        board_size: usize,
-        player_model: &mut PlayerModel) -> HashMap<String, Vec<(usize, usize)>> {
-        let mut player_liberties: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
-        for chain_key in player_model.player_chains.keys() {
-            let mut liberties_for_chain: Vec<(usize, usize)> = Vec::new();
-            println!("Checking chain {chain_key}");
-            let chain = match player_model.player_chains.get(chain_key) {
-                Some(chain_val) => chain_val,
-                None => continue,
-            };
-            for chain_friend in chain {
-                println!("Checking space {:?} for liberties", chain_friend);
-                let row = chain_friend.0;
-                let col = chain_friend.1;
-                let adjacent_empty: Vec<(usize, usize)> = get_adjacent(board_state, board_size, row, col, EMPTY);
-                for empty_space in adjacent_empty {
-                    liberties_for_chain.push(empty_space);
-                }
+    pub fn update_adjacent_liberties(
+        board: &Board,
+        player_model: &mut PlayerModel,
+        opponent_model: &mut PlayerModel,
+        row: usize,
+        col: usize,
+        gained: bool,
+    ) {
+        for model in [player_model, opponent_model] {
+            for (adjacent_row, adjacent_col) in get_adjacent(board, row, col, model.player.player_color.into()) {
+                model.change_liberties(board.chain_id_at(adjacent_row, adjacent_col), (row, col), gained);
             }
-            player_liberties.insert(chain_key.to_string(), liberties_for_chain);
-        }            
-        println!("Liberties {:?}", player_model.player_liberties);
-        player_liberties
+        }
+    }
+
+    /**
+     * Same answer as check_for_self_capture, for the player owning player_model
+     * Reads the liberties kept on the player models, so only the chains next to (row,col) are looked at.
+     * The stone is suicide if it has no empty neighbour, captures nothing and
+     * every friendly chain next to it has (row,col) as its last liberty
+     */
+    pub fn is_suicide(
+        board: &Board,
+        player_model: &PlayerModel,
+        opponent_model: &PlayerModel,
+        row: usize,
+        col: usize,
+    ) -> bool {
+        if !get_adjacent(board, row, col, PointState::Empty).is_empty() {
+            return false;
+        }
+        let last_liberty_at_stone = |model: &PlayerModel, (adjacent_row, adjacent_col): (usize, usize)| {
+            model.get_liberties(board.chain_id_at(adjacent_row, adjacent_col))
+                .is_some_and(|liberties| liberties.len() == 1 && liberties.contains(&(row, col)))
+        };
+        // Capturing an opponent chain always leaves the stone a liberty
+        if get_adjacent(board, row, col, opponent_model.player.player_color.into()).into_iter()
+            .any(|opponent| last_liberty_at_stone(opponent_model, opponent)) {
+            return false;
+        }
+        get_adjacent(board, row, col, player_model.player.player_color.into()).into_iter()
+            .all(|friend| last_liberty_at_stone(player_model, friend))
     }
 
     /**
      * Given a placement at position (row,col), check that the 
      * placement of the stone is not going to cause a self capture
+     * Opponent chains left without liberties are captured first, so a move that
+     * captures is never suicide. Otherwise the whole merged chain needs a liberty.
      * return true if the placement would cause self capture
      * return false if the placement is valid
      */
-    pub fn check_for_self_capture(board: &mut Board, row: usize, col: usize) -> bool {
-    // Temporarily place the stone to check if it would result in self-capture
-    let original_color = board.board_state[row][col].player_color;
-    let current_player_color = if board.is_white_turn { WHITE } else { BLACK };
-    board.board_state[row][col].player_color = current_player_color;
-
-    // Get all adjacent stones of the same color to form a temporary chain
-    let mut temp_chain = vec![(row, col)];
-    let friends = get_adjacent(&mut board.board_state, board.board_size, row, col, current_player_color);
-    temp_chain.extend(friends);
-
-    // Check if this temporary chain has any liberties
-    let mut has_liberties = false;
-    for &(stone_row, stone_col) in &temp_chain {
-        let empty_adjacent = get_adjacent(&mut board.board_state, board.board_size, stone_row, stone_col, EMPTY);
-        if !empty_adjacent.is_empty() {
-            has_liberties = true;
-            break;
-        }
-    }
-
-    // Reset the board state
-    board.board_state[row][col].player_color = original_color;
-
-    // Return true if placement would result in self-capture (no liberties)
-    !has_liberties
-    }
-
-    pub fn check_for_conquered(
-        player_model: &mut PlayerModel, 
-        board_state: &mut [Vec<Intersection>]) 
-    -> Vec<String> {
-        let mut removed_chain_keys: Vec<String> = Vec::<String>::new();
-        for (chain_key, liberties) in player_model.player_liberties.iter() {
-            println!("Checking to see if chain id {chain_key} has any liberties...");
-            if liberties.is_empty() {
-                println!("Chain id {chain_key} has no liberties. It has been eliminated.");
-                let captured_chain = match player_model.player_chains.get(chain_key) {
-                    Some(captured) => captured,
-                    None => continue,
-                };
-                for captured_location in captured_chain {
-                    if board_state[captured_location.0][captured_location.1].player_color == WHITE {
-                        board_state[captured_location.0][captured_location.1].player_color = BLACK_TERR;
-                    } else if board_state[captured_location.0][captured_location.1].player_color == BLACK {
-                        board_state[captured_location.0][captured_location.1].player_color = WHITE_TERR;
-                    }
+    pub fn check_for_self_capture(board: &Board, row: usize, col: usize) -> bool {
+        // Resolve opponent captures before looking at our own liberties
+        if !find_captures(board, row, col).is_empty() {
+            return false;
+        }
+        if !get_adjacent(board, row, col, PointState::Empty).is_empty() {
+            return false;
+        }
+
+        // Every friendly chain the stone joins loses (row,col), so one of them needs another liberty
+        !get_adjacent(board, row, col, board.to_move().into()).into_iter().any(|(friend_row, friend_col)| {
+            chain_has_other_liberty(board, &collect_chain(board, friend_row, friend_col), (row, col))
+        })
+    }
+
+    /**
+     * Return the opponent stones that a stone placed at (row,col) by the player to move would capture
+     * The board is left untouched
+     */
+    pub fn find_captures(board: &Board, row: usize, col: usize) -> Vec<(usize, usize)> {
+        let mut captured: Vec<(usize, usize)> = Vec::new();
+        for (opponent_row, opponent_col) in get_adjacent(board, row, col, board.to_move().opponent().into()) {
+            if captured.contains(&(opponent_row, opponent_col)) {
+                continue;
+            }
+            let opponent_chain = collect_chain(board, opponent_row, opponent_col);
+            if !chain_has_other_liberty(board, &opponent_chain, (row, col)) {
+                captured.extend(opponent_chain);
+            }
+        }
+        captured
+    }
+
+    /**
+     * Check whether a stone placed at (row,col) by the player to move breaks the rule set's ko rule
+     * Simple ko forbids retaking at the current ko point. Superko compares the resulting
+     * position against every earlier position, with situational superko also matching the side to move.
+     * Returns None if the move is allowed or the point is occupied
+     */
+    pub fn check_ko(board: &Board, row: usize, col: usize) -> Option<KoViolation> {
+        if !board.point_state(row, col).is_empty() {
+            return None;
+        }
+        if board.ko_point == Some((row, col)) {
+            return Some(KoViolation::SimpleKo);
+        }
+        if board.rules.ko_rule == KoRule::Simple {
+            return None;
+        }
+
+        let keys = zobrist::table();
+        let player_stone = PointState::from(board.to_move());
+        let opponent_stone = PointState::from(board.to_move().opponent());
+        let mut next_hash = board.hash ^ keys.stone(board.point_index(row, col), player_stone);
+        for (captured_row, captured_col) in find_captures(board, row, col) {
+            next_hash ^= keys.stone(board.point_index(captured_row, captured_col), opponent_stone);
+        }
+        if board.rules.suicide_allowed && check_for_self_capture(board, row, col) {
+            // A suicide leaves the board without the player's own merged chain
+            let mut own_stones = vec![(row, col)];
+            for (friend_row, friend_col) in get_adjacent(board, row, col, player_stone) {
+                if !own_stones.contains(&(friend_row, friend_col)) {
+                    own_stones.extend(collect_chain(board, friend_row, friend_col));
                 }
-                removed_chain_keys.push(chain_key.to_string());
+            }
+            for (own_row, own_col) in own_stones {
+                next_hash ^= keys.stone(board.point_index(own_row, own_col), player_stone);
             }
         }
-        
-        println!("The following chains were removed {:?}", removed_chain_keys);
-        //TODO: call update_prisoners as needed
-        removed_chain_keys
+        let next_white_turn = !board.is_white_turn;
+
+        for (hash, white_turn) in &board.position_history {
+            if *hash != next_hash {
+                continue;
+            }
+            match board.rules.ko_rule {
+                KoRule::PositionalSuperko => return Some(KoViolation::PositionalSuperko),
+                KoRule::SituationalSuperko if *white_turn == next_white_turn => {
+                    return Some(KoViolation::SituationalSuperko)
+                }
+                _ => {}
+            }
+        }
+        None
     }
-    
+
+    /**
+     * Call after a stone at (row,col) has captured and been merged into its chain
+     * A single stone that captured a single stone and is left with that point as its only
+     * liberty can be retaken immediately, so that point becomes the ko point
+     */
+    fn find_ko_point(board: &Board, row: usize, col: usize, captures: &[(usize, usize)]) -> Option<(usize, usize)> {
+        if captures.len() != 1 {
+            return None;
+        }
+        let friends = get_adjacent(board, row, col, board.point_state(row, col));
+        let liberties = get_adjacent(board, row, col, PointState::Empty);
+        if friends.is_empty() && liberties == captures {
+            Some(captures[0])
+        } else {
+            None
+        }
+    }
+
+    /**
+     * Flood fill from (row,col) and return every stone connected to it with the same color
+     * This only looks at the board itself, not at the chains kept on the player models
+     */
+    pub fn collect_chain(board: &Board, row: usize, col: usize) -> Vec<(usize, usize)> {
+        let chain_state = board.point_state(row, col);
+        let mut visited = vec![false; board.width * board.height];
+        visited[board.point_index(row, col)] = true;
+        let mut chain: Vec<(usize, usize)> = vec![(row, col)];
+        let mut to_visit: Vec<(usize, usize)> = vec![(row, col)];
+        while let Some((visit_row, visit_col)) = to_visit.pop() {
+            for friend in get_adjacent(board, visit_row, visit_col, chain_state) {
+                let friend_index = board.point_index(friend.0, friend.1);
+                if !visited[friend_index] {
+                    visited[friend_index] = true;
+                    chain.push(friend);
+                    to_visit.push(friend);
+                }
+            }
+        }
+        chain
+    }
+
+    /**
+     * Return true if any stone of the chain touches an empty intersection other than point
+     */
+    pub fn chain_has_other_liberty(board: &Board, chain: &[(usize, usize)], point: (usize, usize)) -> bool {
+        chain.iter().any(|&(stone_row, stone_col)| {
+            get_adjacent(board, stone_row, stone_col, PointState::Empty).into_iter().any(|liberty| liberty != point)
+        })
+    }
+
+    /**
+     * Take a chain without liberties off the board so its points can be played again
+     * owner_model owns the chain, the stones give their liberties back to both players' chains
+     * Returns the removed stones
+     */
+    pub fn remove_chain(
+        board: &mut Board,
+        owner_model: &mut PlayerModel,
+        other_model: &mut PlayerModel,
+        chain_id: usize,
+    ) -> Vec<(usize, usize)> {
+        let stones = match owner_model.remove_player_chain(chain_id) {
+            Some((stones, _)) => stones,
+            None => return Vec::new(),
+        };
+        for &(row, col) in &stones {
+            board.update_board_color(row, col, PointState::Empty);
+            board.set_chain_id(row, col, board.point_index(row, col));
+        }
+        for &(row, col) in &stones {
+            update_adjacent_liberties(board, owner_model, other_model, row, col, true);
+        }
+        stones
+    }
+
     /**
-     * Cleanup the player models that have lose chains
+     * Credit the player with capturing_color with captured_count prisoners
      */
-    pub fn cleanup_captured(player_model: &mut PlayerModel, removed_chain_keys: Vec<String>) {
-        for chain_key in removed_chain_keys {
-            player_model.remove_player_chain(&chain_key);
-            player_model.remove_player_liberties(&chain_key);
+    pub fn update_prisoners(board: &mut Board, capturing_color: Color, captured_count: usize) {
+        match capturing_color {
+            Color::White => board.white_captured += captured_count as u32,
+            Color::Black => board.black_captured += captured_count as u32,
         }
     }
 
-#[derive(Debug)]
-#[derive(Component)]
-pub(crate) struct PlayerModel {
-    player_chains: HashMap<String, Vec<(usize, usize)>>,
-    player_liberties: HashMap<String, Vec<(usize, usize)>>,
+/**
+ * Build both players' chains and liberties again from the stones on the board
+ * Used after undo, where splitting merged chains apart again is harder than starting over
+ */
+pub fn rebuild_chains(board: &mut Board, black_model: &mut PlayerModel, white_model: &mut PlayerModel) {
+    *black_model = PlayerModel::new(Color::Black);
+    *white_model = PlayerModel::new(Color::White);
+    board.chain_ids = (0..board.width * board.height).collect();
+    for row in 0..board.height {
+        for col in 0..board.width {
+            let chain_id = board.point_index(row, col);
+            // Stones of a chain already built point at its first stone instead of themselves
+            let color = match board.point_state(row, col).stone() {
+                Some(color) if board.chain_id_at(row, col) == chain_id => color,
+                _ => continue,
+            };
+            let chain = collect_chain(board, row, col);
+            let liberties = chain.iter()
+                .flat_map(|&(stone_row, stone_col)| get_adjacent(board, stone_row, stone_col, PointState::Empty))
+                .collect();
+            for &(stone_row, stone_col) in &chain {
+                board.set_chain_id(stone_row, stone_col, chain_id);
+            }
+            let model = match color {
+                Color::White => &mut *white_model,
+                Color::Black => &mut *black_model,
+            };
+            model.add_player_chain(chain_id, chain, liberties);
+        }
+    }
+}
+
+/**
+ * The chains a player has on the board, keyed by chain id
+ * A chain id is the point index of one of its stones, see Board::point_index.
+ * player_liberties holds, for each chain, the empty points next to any of its stones.
+ */
+#[derive(Debug, Clone)]
+pub struct PlayerModel {
+    player_chains: HashMap<usize, Vec<(usize, usize)>>,
+    player_liberties: HashMap<usize, HashSet<(usize, usize)>>,
     player: Player,
 }
 
 impl PlayerModel {
-    pub fn new(player_color: u8) -> Self {
-        let (new_player_color, opponent_color) = if player_color == 1 {
-            (1, 2)
-        } else {
-            (2, 1)
-        };
+    pub fn new(player_color: Color) -> Self {
         PlayerModel {
             player_chains: HashMap::new(),
             player_liberties: HashMap::new(),
-            player: Player::new(new_player_color, opponent_color),
+            player: Player::new(player_color, player_color.opponent()),
         }
     }
 
-    pub fn get_player_color(&self) -> u8 {
+    pub fn get_player_color(&self) -> Color {
         self.player.player_color
     }
 
-    #[allow(dead_code)]
-    fn update_player_chains(&mut self, chain_key: &str, index: usize, new_value: (usize, usize)) {
-        if let Some(vec) = self.player_chains.get_mut(chain_key) {
-            if let Some(pair) = vec.get_mut(index) {
-                *pair = new_value;
+    pub fn get_liberties(&self, chain_id: usize) -> Option<&HashSet<(usize, usize)>> {
+        self.player_liberties.get(&chain_id)
+    }
+
+    fn chain_len(&self, chain_id: usize) -> usize {
+        self.player_chains.get(&chain_id).map_or(0, |chain| chain.len())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn remove_player_chain(&mut self, chain_id: usize) -> Option<(Vec<(usize, usize)>, HashSet<(usize, usize)>)> {
+        let chain = self.player_chains.remove(&chain_id)?;
+        let liberties = self.player_liberties.remove(&chain_id).unwrap_or_default();
+        Some((chain, liberties))
+    }
+
+    fn add_player_chain(&mut self, chain_id: usize, new_chain: Vec<(usize, usize)>, liberties: HashSet<(usize, usize)>) {
+        self.player_chains.entry(chain_id).or_default()
+            .extend(new_chain);
+        self.player_liberties.entry(chain_id).or_default()
+            .extend(liberties);
+    }
+
+    fn change_liberties(&mut self, chain_id: usize, point: (usize, usize), gained: bool) {
+        if let Some(liberties) = self.player_liberties.get_mut(&chain_id) {
+            if gained {
+                liberties.insert(point);
+            } else {
+                liberties.remove(&point);
             }
         }
     }
+}
+
+/**
+ * Which repetition rule the board enforces
+ * Simple only forbids the immediate recapture of a ko.
+ * Positional superko forbids recreating any earlier position,
+ * situational superko only when the same player would be to move.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KoRule {
+    Simple,
+    PositionalSuperko,
+    SituationalSuperko,
+}
+
+/**
+ * Reason a move was refused by the ko rule
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KoViolation {
+    SimpleKo,
+    PositionalSuperko,
+    SituationalSuperko,
+}
+
+impl fmt::Display for KoViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KoViolation::SimpleKo => write!(f, "Ko: the stone cannot be retaken immediately"),
+            KoViolation::PositionalSuperko => write!(f, "Superko: the move repeats an earlier position"),
+            KoViolation::SituationalSuperko => write!(f, "Superko: the move repeats an earlier position with the same player to move"),
+        }
+    }
+}
 
-    fn remove_player_chain(&mut self, chain_key: &str) -> Option<(String, Vec<(usize, usize)>)> {
-        if let Some(vec) = self.player_chains.remove_entry(chain_key) {
-            return Some(vec);
+/**
+ * Reason place_stone refused a move
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    OutOfBounds,
+    Occupied,
+    WrongTurn,
+    Suicide,
+    Ko(KoViolation),
+    GameOver,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::OutOfBounds => write!(f, "That point is not on the board"),
+            MoveError::Occupied => write!(f, "That point is already occupied"),
+            MoveError::WrongTurn => write!(f, "It is not that player's turn"),
+            MoveError::Suicide => write!(f, "Suicide: the stone would have no liberties"),
+            MoveError::Ko(violation) => write!(f, "{violation}"),
+            MoveError::GameOver => write!(f, "The game is over"),
         }
-        None
     }
+}
 
-    #[allow(dead_code)]
-    fn remove_player_chain_item(&mut self, chain_key: &str, index: usize) -> Option<(usize, usize)> {
-        if let Some(vec) = self.player_chains.get_mut(chain_key) {
-            if index < vec.len() {
-                return Some(vec.remove(index));
+/**
+ * What a successful place_stone changed on the board
+ * captured holds the opponent stones taken off the board,
+ * chain holds every stone of the chain the new stone now belongs to,
+ * suicide holds the player's own stones taken off the board when the rules allow suicide
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MoveOutcome {
+    pub captured: Vec<(usize, usize)>,
+    pub chain: Vec<(usize, usize)>,
+    pub suicide: Vec<(usize, usize)>,
+}
+
+/**
+ * Stage of the game
+ * Playing until two consecutive passes, then Scoring until the result is settled
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamePhase {
+    Playing,
+    Scoring,
+    Finished,
+}
+
+/**
+ * How a finished game was decided
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameResult {
+    Resignation { winner: Color },
+    Score { winner: Color, margin: f32 },
+    Draw,
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameResult::Resignation { winner } => {
+                write!(f, "{}+R", winner.letter())
+            }
+            GameResult::Score { winner, margin } => {
+                write!(f, "{}+{}", winner.letter(), margin)
             }
+            GameResult::Draw => write!(f, "Draw"),
         }
-        None
     }
+}
 
-    fn add_player_chain(&mut self, chain_key: &str, new_chain: Vec<(usize, usize)>) {
-            self.player_chains.entry(chain_key.to_string()).or_default()
-            .extend(new_chain);
+/**
+ * How the final position is counted
+ * Area counts stones plus territory, Territory counts territory plus prisoners
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoringMethod {
+    Area,
+    Territory,
+}
+
+/**
+ * How white is compensated for handicap stones under area scoring
+ * PerStone gives white one point per handicap stone, PerStoneAfterFirst one point
+ * for every stone after the first
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandicapKomi {
+    None,
+    PerStone,
+    PerStoneAfterFirst,
+}
+
+/**
+ * The rules a game is played under
+ * suicide_allowed lets a move remove its own chain when it captures nothing.
+ * pass_stones hands the opponent a prisoner for every pass and makes white pass last,
+ * which keeps territory and area counting in agreement.
+ * free_handicap lets black choose where the handicap stones go instead of the star points.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RuleSet {
+    pub name: &'static str,
+    pub suicide_allowed: bool,
+    pub ko_rule: KoRule,
+    pub scoring_method: ScoringMethod,
+    pub komi: f32,
+    pub pass_stones: bool,
+    pub handicap_komi: HandicapKomi,
+    pub free_handicap: bool,
+}
+
+impl RuleSet {
+    pub fn aga() -> Self {
+        RuleSet {
+            name: "AGA",
+            suicide_allowed: false,
+            ko_rule: KoRule::SituationalSuperko,
+            scoring_method: ScoringMethod::Area,
+            komi: 7.5,
+            pass_stones: true,
+            handicap_komi: HandicapKomi::PerStoneAfterFirst,
+            free_handicap: false,
+        }
+    }
+
+    pub fn chinese() -> Self {
+        RuleSet {
+            name: "Chinese",
+            suicide_allowed: false,
+            ko_rule: KoRule::PositionalSuperko,
+            scoring_method: ScoringMethod::Area,
+            komi: 7.5,
+            pass_stones: false,
+            handicap_komi: HandicapKomi::PerStone,
+            free_handicap: true,
+        }
+    }
+
+    pub fn japanese() -> Self {
+        RuleSet {
+            name: "Japanese",
+            suicide_allowed: false,
+            ko_rule: KoRule::Simple,
+            scoring_method: ScoringMethod::Territory,
+            komi: 6.5,
+            pass_stones: false,
+            handicap_komi: HandicapKomi::None,
+            free_handicap: false,
+        }
+    }
+
+    pub fn new_zealand() -> Self {
+        RuleSet {
+            name: "New Zealand",
+            suicide_allowed: true,
+            ko_rule: KoRule::SituationalSuperko,
+            scoring_method: ScoringMethod::Area,
+            komi: 7.0,
+            pass_stones: false,
+            handicap_komi: HandicapKomi::None,
+            free_handicap: true,
+        }
+    }
+
+    pub fn tromp_taylor() -> Self {
+        RuleSet {
+            name: "Tromp-Taylor",
+            suicide_allowed: true,
+            ko_rule: KoRule::PositionalSuperko,
+            scoring_method: ScoringMethod::Area,
+            komi: 7.5,
+            pass_stones: false,
+            handicap_komi: HandicapKomi::None,
+            free_handicap: true,
+        }
+    }
+
+    /**
+     * Every preset, in the order the board offers them
+     */
+    pub fn presets() -> [RuleSet; 5] {
+        [Self::chinese(), Self::japanese(), Self::aga(), Self::new_zealand(), Self::tromp_taylor()]
+    }
+
+    /**
+     * Points white receives on top of HANDICAP_KOMI for a game with this many handicap stones
+     */
+    pub fn handicap_compensation(&self, handicap: usize) -> f32 {
+        match self.handicap_komi {
+            HandicapKomi::None => 0.0,
+            HandicapKomi::PerStone => handicap as f32,
+            HandicapKomi::PerStoneAfterFirst => handicap.saturating_sub(1) as f32,
+        }
+    }
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self::chinese()
+    }
+}
+
+/**
+ * Status the players agree on for a chain during the scoring phase
+ * Dead chains are removed as prisoners, chains in seki stay alive but
+ * the empty points next to them count for nobody under territory scoring
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainStatus {
+    Alive,
+    Dead,
+    Seki,
+}
+
+/**
+ * width is the number of columns and height the number of rows, equal on a square board
+ * points holds what is on every intersection row by row, see point_index
+ * chain_ids holds the id of the chain each stone belongs to, an empty point is its own chain
+ * Both are flat arrays, so a board is cheap to clone for playouts. Use point_state and chain_id_at to read them.
+ * white_captured and black_captured are the prisoners taken by white and black respectively
+ * hash is the Zobrist hash of the stones on the board, kept up to date as stones are placed and removed
+ * position_history holds the hash of every position reached so far, together with whether white was to move
+ * rules decides suicide, ko, scoring, komi and handicap, and is fixed once the game starts
+ * chain_status holds the stones marked dead or in seki during scoring, unmarked stones are alive
+ * Black moves first unless handicap stones have been placed
+ */
+#[derive(Clone)]
+pub struct Board {
+    pub width: usize,
+    pub height: usize,
+    points: Vec<PointState>,
+    chain_ids: Vec<usize>,
+    pub white_captured: u32,
+    pub black_captured: u32,
+    pub is_white_turn: bool,
+    pub rules: RuleSet,
+    pub ko_point: Option<(usize, usize)>,
+    pub hash: u64,
+    pub position_history: Vec<(u64, bool)>,
+    pub consecutive_passes: u8,
+    pub phase: GamePhase,
+    pub result: Option<GameResult>,
+    pub chain_status: HashMap<(usize, usize), ChainStatus>,
+    pub handicap: usize,
+}
+
+impl Board {
+    /**
+     * Index of (row,col) when the board is read row by row, also used as a chain id
+     */
+    pub fn point_index(&self, row: usize, col: usize) -> usize {
+        row * self.width + col
+    }
+
+    pub fn is_on_board(&self, row: usize, col: usize) -> bool {
+        row < self.height && col < self.width
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.width == self.height
+    }
+
+    /**
+     * Every intersection of the board as (row, col), row by row
+     */
+    pub fn intersections(&self) -> impl Iterator<Item = (usize, usize)> {
+        let width = self.width;
+        (0..self.height).flat_map(move |row| (0..width).map(move |col| (row, col)))
+    }
+
+    pub fn point_state(&self, row: usize, col: usize) -> PointState {
+        self.points[self.point_index(row, col)]
+    }
+
+    /**
+     * The player whose turn it is
+     */
+    pub fn to_move(&self) -> Color {
+        if self.is_white_turn { Color::White } else { Color::Black }
+    }
+
+    pub fn chain_id_at(&self, row: usize, col: usize) -> usize {
+        self.chain_ids[self.point_index(row, col)]
+    }
+
+    fn set_chain_id(&mut self, row: usize, col: usize, chain_id: usize) {
+        let point_index = self.point_index(row, col);
+        self.chain_ids[point_index] = chain_id;
+    }
+
+    /**
+     * Every stone on the board as (row, col, color), row by row
+     */
+    pub fn stones(&self) -> Vec<(usize, usize, Color)> {
+        self.intersections()
+            .filter_map(|(row, col)| self.point_state(row, col).stone().map(|color| (row, col, color)))
+            .collect()
+    }
+
+    /**
+     * The points left, right, above and below (row,col) that are on the board
+     */
+    pub fn adjacent_points(&self, row: usize, col: usize) -> impl Iterator<Item = (usize, usize)> {
+        [
+            (col > 0).then(|| (row, col - 1)),
+            (col + 1 < self.width).then_some((row, col + 1)),
+            (row > 0).then(|| (row - 1, col)),
+            (row + 1 < self.height).then_some((row + 1, col)),
+        ].into_iter().flatten()
+    }
+
+    pub fn new(board_size: usize) -> Self {
+        Self::with_rules(board_size, RuleSet::default())
+    }
+
+    pub fn with_rules(board_size: usize, rules: RuleSet) -> Self {
+        Self::with_dimensions(board_size, board_size, rules)
+    }
+
+    /**
+     * A board of width columns and height rows, which need not be square
+     */
+    pub fn with_dimensions(width: usize, height: usize, rules: RuleSet) -> Self {
+        let sizes = MIN_BOARD_SIZE..=MAX_BOARD_SIZE;
+        assert!(
+            sizes.contains(&width) && sizes.contains(&height),
+            "board sides are {MIN_BOARD_SIZE} to {MAX_BOARD_SIZE} points long"
+        );
+        let mut board = Board {
+            width,
+            height,
+            points: vec![PointState::Empty; width * height],
+            //All intersections start with their chain being just themselves
+            chain_ids: (0..width * height).collect(),
+            white_captured: 0,
+            black_captured: 0,
+            is_white_turn: false,
+            rules,
+            ko_point: None,
+            hash: 0,
+            position_history: Vec::new(),
+            consecutive_passes: 0,
+            phase: GamePhase::Playing,
+            result: None,
+            chain_status: HashMap::new(),
+            handicap: 0,
+        };
+        board.position_history.push((board.hash, board.is_white_turn));
+        board
+    }
+
+    /**
+     * Every stone color change goes through here so the hash stays in step with the board
+     */
+    pub fn update_board_color(&mut self, row: usize, col: usize, state: PointState) {
+        let keys = zobrist::table();
+        let point_index = self.point_index(row, col);
+        self.hash ^= keys.stone(point_index, self.points[point_index]) ^ keys.stone(point_index, state);
+        self.points[point_index] = state;
+    }
+
+    /**
+     * Hash of the stones plus the side to move, for situational superko and bot transposition tables
+     */
+    pub fn situational_hash(&self) -> u64 {
+        if self.is_white_turn {
+            self.hash ^ zobrist::table().white_to_move()
+        } else {
+            self.hash
+        }
+    }
+
+    /**
+     * Situational hash that also includes the current ko point, if there is one
+     * Two positions with the same hash here allow exactly the same moves
+     */
+    pub fn full_hash(&self) -> u64 {
+        match self.ko_point {
+            Some((row, col)) => self.situational_hash() ^ zobrist::table().ko_point(self.point_index(row, col)),
+            None => self.situational_hash(),
+        }
+    }
+
+    /**
+     * Hash of the stones worked out from scratch, the same value hash is kept at
+     */
+    pub fn compute_hash(&self) -> u64 {
+        let keys = zobrist::table();
+        self.points.iter().enumerate()
+            .fold(0, |hash, (point_index, &state)| hash ^ keys.stone(point_index, state))
+    }
+
+    /**
+     * Status agreed for the stone at (row,col), alive unless marked otherwise
+     */
+    pub fn get_chain_status(&self, row: usize, col: usize) -> ChainStatus {
+        self.chain_status.get(&(row, col)).copied().unwrap_or(ChainStatus::Alive)
+    }
+
+    /**
+     * True once any move or pass has been made after the starting position
+     */
+    pub fn has_started(&self) -> bool {
+        self.position_history.len() > 1 || self.consecutive_passes > 0 || self.phase != GamePhase::Playing
+    }
+
+    /**
+     * The komi of this game, the rule set's komi in an even game and HANDICAP_KOMI with handicap stones
+     */
+    pub fn komi(&self) -> f32 {
+        if self.handicap > 0 {
+            HANDICAP_KOMI
+        } else {
+            self.rules.komi
+        }
+    }
+
+    /**
+     * Komi plus any handicap compensation the rules give white
+     * The compensation replaces the even-game komi rather than adding to it, see komi
+     */
+    pub fn white_compensation(&self) -> f32 {
+        self.komi() + self.rules.handicap_compensation(self.handicap)
+    }
+
+    pub fn toggle_turn(&mut self) {
+        self.is_white_turn = !self.is_white_turn;
+    }
+
+}
+
+/**
+ * A move made by the player whose turn it is
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Move {
+    Play(usize, usize),
+    Pass,
+    Resign,
+}
+
+/**
+ * A move in the game's move list, with what is needed to take it back
+ * captured and suicide hold the stones the move took off the board,
+ * the remaining fields hold the board state from before the move
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveRecord {
+    pub color: Color,
+    pub played: Move,
+    pub captured: Vec<(usize, usize)>,
+    pub suicide: Vec<(usize, usize)>,
+    pub ko_point: Option<(usize, usize)>,
+    pub consecutive_passes: u8,
+    pub white_captured: u32,
+    pub black_captured: u32,
+    pub phase: GamePhase,
+    pub result: Option<GameResult>,
+}
+
+/**
+ * Owns a whole game: the board, both players' chain and liberty data and the moves played.
+ * play() always acts for the player to move, so there is no way to move out of turn
+ * or to hand place_stone the wrong pair of player models.
+ */
+#[derive(Clone)]
+pub struct GameState {
+    board: Board,
+    black: PlayerModel,
+    white: PlayerModel,
+    moves: Vec<MoveRecord>,
+    redo_moves: Vec<Move>,
+}
+
+impl GameState {
+    pub fn new(board_size: usize) -> Self {
+        Self::with_rules(board_size, RuleSet::default())
+    }
+
+    /**
+     * Start a game under the given rule set
+     */
+    pub fn with_rules(board_size: usize, rules: RuleSet) -> Self {
+        Self::with_dimensions(board_size, board_size, rules)
+    }
+
+    /**
+     * Start a game on a board of width columns and height rows
+     */
+    pub fn with_dimensions(width: usize, height: usize, rules: RuleSet) -> Self {
+        GameState {
+            board: Board::with_dimensions(width, height, rules),
+            black: PlayerModel::new(Color::Black),
+            white: PlayerModel::new(Color::White),
+            moves: Vec::new(),
+            redo_moves: Vec::new(),
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn player(&self, player_color: Color) -> &PlayerModel {
+        match player_color {
+            Color::White => &self.white,
+            Color::Black => &self.black,
+        }
+    }
+
+    /**
+     * Color of the player whose turn it is
+     */
+    pub fn to_move(&self) -> Color {
+        self.board.to_move()
+    }
+
+    /**
+     * Every move accepted so far, with the color that made it
+     */
+    pub fn moves(&self) -> Vec<(Color, Move)> {
+        self.moves.iter().map(|record| (record.color, record.played)).collect()
+    }
+
+    /**
+     * The move list with the captures and prior state stored for undo
+     */
+    pub fn history(&self) -> &[MoveRecord] {
+        &self.moves
+    }
+
+    /**
+     * Make a move for the player to move
+     * A pass or resignation has no captured stones and no chain in its outcome
+     * Playing a move drops the moves that could have been redone
+     */
+    pub fn play(&mut self, next_move: Move) -> Result<MoveOutcome, MoveError> {
+        let outcome = self.make_move(next_move, false)?;
+        self.redo_moves.clear();
+        Ok(outcome)
     }
 
-    #[allow(dead_code)]
-    fn add_player_chain_item(&mut self, chain_key: &str, item: (usize, usize)) {
-        self.player_chains.entry(chain_key.to_string()).or_default()
-        .push(item);
+    /**
+     * Make a move for the player to move even after both players passed
+     * Game records and GTP controllers carry on after the passes to settle dead stones, so
+     * during scoring the marked stones are dropped and play resumes until the next two passes in a row.
+     * Undo goes back to the scoring phase.
+     */
+    pub fn play_on(&mut self, next_move: Move) -> Result<MoveOutcome, MoveError> {
+        let outcome = self.make_move(next_move, true)?;
+        self.redo_moves.clear();
+        Ok(outcome)
+    }
+
+    fn make_move(&mut self, next_move: Move, resume: bool) -> Result<MoveOutcome, MoveError> {
+        let player_color = self.to_move();
+        let mut record = MoveRecord {
+            color: player_color,
+            played: next_move,
+            captured: Vec::new(),
+            suicide: Vec::new(),
+            ko_point: self.board.ko_point,
+            consecutive_passes: self.board.consecutive_passes,
+            white_captured: self.board.white_captured,
+            black_captured: self.board.black_captured,
+            phase: self.board.phase,
+            result: self.board.result,
+        };
+        let resumed = resume && self.board.phase == GamePhase::Scoring;
+        // The passes still count, so a stone needs two more passes to end play and another pass ends it again
+        let chain_status = if resumed {
+            self.board.phase = GamePhase::Playing;
+            std::mem::take(&mut self.board.chain_status)
+        } else {
+            HashMap::new()
+        };
+        let played = match next_move {
+            Move::Play(row, col) => {
+                let (player_model, opponent_model) = match player_color {
+                    Color::White => (&mut self.white, &mut self.black),
+                    Color::Black => (&mut self.black, &mut self.white),
+                };
+                place_stone(&mut self.board, player_model, opponent_model, row, col)
+            }
+            Move::Pass if pass(&mut self.board) => Ok(MoveOutcome::default()),
+            Move::Resign if resign(&mut self.board, player_color) => Ok(MoveOutcome::default()),
+            Move::Pass | Move::Resign => Err(MoveError::GameOver),
+        };
+        let outcome = match played {
+            Ok(outcome) => outcome,
+            Err(error) => {
+                // A refused move leaves the game in scoring as it was
+                if resumed {
+                    self.board.phase = record.phase;
+                    self.board.chain_status = chain_status;
+                }
+                return Err(error);
+            }
+        };
+        record.captured = outcome.captured.clone();
+        record.suicide = outcome.suicide.clone();
+        self.moves.push(record);
+        Ok(outcome)
+    }
+
+    /**
+     * Take back the last move, putting back the stones it captured and the prisoners,
+     * ko point, passes and phase from before it
+     * The move can be played again with redo until another move is played.
+     * Returns false if there is no move to take back
+     */
+    pub fn undo(&mut self) -> bool {
+        let record = match self.moves.pop() {
+            Some(record) => record,
+            None => return false,
+        };
+        if let Move::Play(row, col) = record.played {
+            // A suicide also removed the new stone, so put the chain back before lifting it
+            for &(suicide_row, suicide_col) in &record.suicide {
+                self.board.update_board_color(suicide_row, suicide_col, record.color.into());
+            }
+            self.board.update_board_color(row, col, PointState::Empty);
+            for &(captured_row, captured_col) in &record.captured {
+                self.board.update_board_color(captured_row, captured_col, record.color.opponent().into());
+            }
+            self.board.position_history.pop();
+            rebuild_chains(&mut self.board, &mut self.black, &mut self.white);
+        }
+        let board = &mut self.board;
+        board.ko_point = record.ko_point;
+        board.consecutive_passes = record.consecutive_passes;
+        board.white_captured = record.white_captured;
+        board.black_captured = record.black_captured;
+        board.phase = record.phase;
+        board.result = record.result;
+        board.is_white_turn = record.color == Color::White;
+        if board.phase == GamePhase::Playing {
+            board.chain_status.clear();
+        }
+        move_log!("Undo {:?}", record.played);
+        self.redo_moves.push(record.played);
+        true
     }
 
-    #[allow(dead_code)]
-    fn update_player_liberties(&mut self, chain_key: &str, index: usize, new_value: (usize, usize)) {
-        if let Some(vec) = self.player_chains.get_mut(chain_key) {
-            if let Some(pair) = vec.get_mut(index) {
-                *pair = new_value;
+    /**
+     * Play the last move taken back by undo again
+     * Returns false if there is no move to redo or it can no longer be played, the move is kept then
+     */
+    pub fn redo(&mut self) -> bool {
+        match self.redo_moves.last().copied() {
+            // A move taken back from after the passes resumes play again
+            Some(next_move) if self.make_move(next_move, true).is_ok() => {
+                self.redo_moves.pop();
+                true
             }
+            _ => false,
         }
     }
 
-
-    fn remove_player_liberties(&mut self, chain_key: &str) -> Option<(String, Vec<(usize, usize)>)> {
-        if let Some(vec) = self.player_liberties.remove_entry(chain_key) {
-            return Some(vec);
+    /**
+     * Put stones on the board or clear points without playing a move, as in a problem diagram
+     * Each entry is (row, col, state), with PointState::Empty to clear the point.
+     * Setup cannot be undone, so it also drops the undo and redo moves.
+     */
+    pub fn setup(&mut self, stones: &[(usize, usize, PointState)]) {
+        for &(row, col, state) in stones {
+            self.board.update_board_color(row, col, state);
         }
-        None
-    }
-
-    #[allow(dead_code)]
-    fn remove_player_liberties_item(&mut self, chain_key: &str, index: usize) -> Option<(usize, usize)> {
-            if let Some(vec) = self.player_liberties.get_mut(chain_key) {
-                if index < vec.len() {
-                    return Some(vec.remove(index));
-                }
-            }
-        None
+        rebuild_chains(&mut self.board, &mut self.black, &mut self.white);
+        self.board.ko_point = None;
+        self.board.position_history.push((self.board.hash, self.board.is_white_turn));
+        self.moves.clear();
+        self.redo_moves.clear();
     }
 
-    #[allow(dead_code)]
-    fn add_player_liberties(&mut self, chain_key: &str, new_liberties: Vec<(usize, usize)>) {
-        self.player_liberties.entry(chain_key.to_string()).or_default()
-            .extend(new_liberties);
+    /**
+     * Change the komi of the game in progress, as GTP allows at any time
+     */
+    pub fn set_komi(&mut self, komi: f32) {
+        self.board.rules.komi = komi;
     }
 
-    #[allow(dead_code)]
-    fn add_player_liberties_item(&mut self, chain_key: &str, item: (usize, usize)) {
-        self.player_liberties.entry(chain_key.to_string()).or_default()
-        .push(item);
+    /**
+     * Hand the turn to player_color, for a diagram or record where the same color moves twice
+     */
+    pub fn set_to_move(&mut self, player_color: Color) {
+        self.board.is_white_turn = player_color == Color::White;
     }
 
-    fn set_player_liberties(&mut self, updated_liberties: HashMap<String, Vec<(usize, usize)>>) {
-        self.player_liberties = updated_liberties;
+    /**
+     * Whether the player to move may place a stone at (row,col), and why not if they may not
+     * The board is not changed, see check_move
+     */
+    pub fn check_play(&self, row: usize, col: usize) -> Result<(), MoveError> {
+        let (player_model, opponent_model) = match self.to_move() {
+            Color::White => (&self.white, &self.black),
+            Color::Black => (&self.black, &self.white),
+        };
+        check_move(&self.board, player_model, opponent_model, row, col)
     }
 
-}
-/**
- * board_state represents the board using 2D vectors
- * 0 means onocuppied
- * 1 means white stone
- * 2 means black stone
- */
-#[derive(Component)]
- pub(crate) struct Board {
-    pub board_size: usize,
-    pub board_state: Vec<Vec<Intersection>>,
-    #[allow(dead_code)]
-    pub white_captured: u8,
-    #[allow(dead_code)]
-    pub black_captured: u8,
-    pub is_white_turn: bool,
-}
-
-impl Board {
-    pub fn generate_id(row: usize, col: usize) -> String {
-        row.to_string() + "_" + &col.to_string()
+    /**
+     * Every point the player to move may place a stone on, row by row
+     * Passing and resigning are always allowed while the game is played and are not listed
+     */
+    pub fn legal_moves(&self) -> Vec<(usize, usize)> {
+        self.board.intersections()
+            .filter(|&(row, col)| self.check_play(row, col).is_ok())
+            .collect()
     }
 
-    /**board_connections
-     * Build a board filled with empty intersections
-     * All Intersections start with their chain being just themselves.
+    /**
+     * Place fixed handicap stones for black, see place_fixed_handicap
      */
-    pub fn build_board_start(board_size: usize) -> Vec<Vec<Intersection>> {
-        let mut b_rows = Vec::new();
-        for row in 0..=board_size-1 {
-            let mut b_columns = Vec::new();
-            for column in 0..=board_size-1 {
-                let next_intersection: Intersection = Intersection::new(row, column);
-                b_columns.push(next_intersection);
-            }
-            b_rows.push(b_columns);
-        }
-        b_rows
+    pub fn place_fixed_handicap(&mut self, stones: usize) -> bool {
+        place_fixed_handicap(&mut self.board, &mut self.black, &mut self.white, stones)
     }
 
-    pub fn new(board_size: usize) -> Self {
-        Board {
-            board_size,
-            board_state: Self::build_board_start(board_size),
-            white_captured: 0,
-            black_captured: 0,
-            is_white_turn: true,
+    /**
+     * Place free handicap stones for black, see place_handicap
+     * Returns false if the rule set only allows fixed handicap
+     */
+    pub fn place_handicap(&mut self, points: &[(usize, usize)]) -> bool {
+        if !self.board.rules.free_handicap {
+            return false;
         }
+        place_handicap(&mut self.board, &mut self.black, &mut self.white, points)
     }
 
-    pub fn update_board_color(&mut self, row: usize, col: usize, color: u8) {
-        self.board_state[row][col].player_color = color;
-    }
-
-    pub fn toggle_turn(&mut self) {
-        self.is_white_turn = !self.is_white_turn;
-    }
-
-}
-
-#[derive(Debug)]
-#[derive(Component)]
-pub(crate) struct Intersection {
-    player_color: u8,
-    chain_id: String,
-    row: usize,
-    col: usize,
-}
-
-impl Intersection {
-    pub fn new(row: usize, col: usize) -> Self {
-        Intersection {
-            player_color: EMPTY, //a new intersection is always an empty intersection
-            chain_id: Board::generate_id(row, col),
-            row,
-            col,
-        }
+    /**
+     * Place handicap stones taken from a game record, see place_handicap
+     * The points were agreed when the game was played, so this also accepts them
+     * under rule sets that only allow fixed handicap
+     */
+    pub fn place_recorded_handicap(&mut self, points: &[(usize, usize)]) -> bool {
+        place_handicap(&mut self.board, &mut self.black, &mut self.white, points)
     }
 
-    pub fn get_player_color(&self) -> u8 {
-        self.player_color
+    /**
+     * During scoring, cycle the chain at (row,col) through alive, dead and seki
+     */
+    pub fn toggle_chain_status(&mut self, row: usize, col: usize) -> bool {
+        scoring::toggle_chain_status(&mut self.board, row, col)
     }
-}
 
-impl fmt::Display for Intersection {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Intersection {{ player_color: {}, chain_id: {}, row: {}, col: {} }}", self.player_color, self.chain_id, self.row, self.col)
+    /**
+     * Accept the marked dead stones and seki, then score and finish the game
+     */
+    pub fn finish_scoring(&mut self) -> Option<GameResult> {
+        scoring::score_game(&mut self.board)
     }
 }
 
-#[derive(Debug)]
-#[derive(Component)]
-pub(crate) struct Player {
-    pub(crate) player_color: u8,
-    #[allow(dead_code)]
-    pub(crate) opponent_color: u8,
+#[derive(Debug, Clone)]
+pub struct Player {
+    pub player_color: Color,
+    pub opponent_color: Color,
 }
 
 impl Player {
-    pub fn new(new_player_color: u8, opponent_color: u8) -> Self {
+    pub fn new(new_player_color: Color, opponent_color: Color) -> Self {
         Player {
             player_color: new_player_color,
             opponent_color,
@@ -435,145 +1566,133 @@ impl Player {
 #[cfg(test)]
 mod tests {
 
+    use std::collections::HashSet;
+
     use crate::game;
-    use crate::game::place_stone;
+    use crate::test_rng::TestRng;
     use crate::game::Board;
+    use crate::game::GameState;
+    use crate::game::Move;
     use crate::game::PlayerModel;
-    use crate::game::BLACK;
-    use crate::game::BLACK_TERR;
-    use crate::game::WHITE;
-    use crate::game::WHITE_TERR;
+    use crate::game::Color;
+    use crate::game::GamePhase;
+    use crate::game::GameResult;
+    use crate::game::KoRule;
+    use crate::game::KoViolation;
+    use crate::game::MoveError;
+    use crate::game::PointState;
+    use crate::game::RuleSet;
+
+    /**
+     * Play the black and white stones in turn, passing for a player who has run out of stones.
+     * Every stone must be legal when it is played.
+     */
+    fn play_stones(state: &mut GameState, black: &[(usize, usize)], white: &[(usize, usize)]) {
+        let mut black_stones = black.iter();
+        let mut white_stones = white.iter();
+        loop {
+            let stones = if state.to_move() == Color::White { &mut white_stones } else { &mut black_stones };
+            let next_move = match stones.next() {
+                Some(&(row, col)) => Move::Play(row, col),
+                None if black_stones.len() + white_stones.len() == 0 => break,
+                None => Move::Pass,
+            };
+            state.play(next_move).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_colors_and_point_states() {
+        assert_eq!(Color::Black.opponent(), Color::White);
+        assert_eq!(Color::White.opponent(), Color::Black);
+        assert_eq!(PointState::from(Color::White).stone(), Some(Color::White));
+        assert_eq!(PointState::Empty.stone(), None);
+        assert!(PointState::default().is_empty());
+        assert_eq!(game::TerritoryOwner::from(Color::Black), game::TerritoryOwner::Black);
+
+        let mut state = GameState::new(5);
+        state.play(Move::Play(1, 1)).unwrap();
+        assert_eq!(state.board().stones(), vec![(1, 1, Color::Black)]);
+        assert_eq!(state.to_move(), Color::White);
+    }
 
     #[test]
     fn test_place_stone() {
-        let mut test_board: Board = Board::new(9);
-        let mut black_player_model = PlayerModel::new(crate::game::BLACK);
-        let mut white_player_model = PlayerModel::new(crate::game::WHITE);
-
-        test_board.toggle_turn();
-        let result1 = game::place_stone(&mut test_board, &mut black_player_model, &mut white_player_model, 2, 2);
-        test_board.toggle_turn();
-        assert_eq!(
-            test_board.board_state[2][2].player_color,
-            crate::game::BLACK
-        );
-        let result2 = game::place_stone(&mut test_board, &mut black_player_model, &mut white_player_model, 8, 8);
-        test_board.toggle_turn();
-        let result3 = game::place_stone(&mut test_board, &mut black_player_model, &mut white_player_model, 8, 8);
-        test_board.toggle_turn();
-        assert_eq!(
-            test_board.board_state[8][8].player_color,
-            crate::game::BLACK
-        );
-        assert_eq!(result1, true);
-        assert_eq!(result2, true);
-        assert_eq!(result3, false);
+        let mut state = GameState::new(9);
+
+        let result1 = state.play(Move::Play(2, 2));
+        assert_eq!(state.board().point_state(2, 2), PointState::Stone(Color::Black));
+        let result2 = state.play(Move::Play(8, 8));
+        let result3 = state.play(Move::Play(8, 8));
+        assert_eq!(state.board().point_state(8, 8), PointState::Stone(Color::White));
+        assert!(result1.is_ok());
+        assert!(result2.is_ok());
+        assert_eq!(result3, Err(MoveError::Occupied));
     }
 
     #[test]
     fn test_check_for_chain() {
-        let mut test_board: Board = Board::new(3);
-
-        let mut black_player_model = PlayerModel::new(crate::game::BLACK);
-        let mut white_player_model = PlayerModel::new(crate::game::WHITE);
-
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 0, 0);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 0, 1);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 0, 2);
-        test_board.toggle_turn();
-        assert_eq!(test_board.board_state[0][0].chain_id, test_board.board_state[0][1].chain_id);
-        assert_eq!(test_board.board_state[0][1].chain_id, test_board.board_state[0][2].chain_id);
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 1, 2);
-        assert_eq!(test_board.board_state[1][2].chain_id, test_board.board_state[0][2].chain_id);
+        let mut state = GameState::new(3);
+        play_stones(&mut state, &[(2, 0), (2, 2)], &[(0, 0), (0, 1), (0, 2)]);
+        let board = state.board();
+        assert_eq!(board.chain_id_at(0, 0), board.chain_id_at(0, 1));
+        assert_eq!(board.chain_id_at(0, 1), board.chain_id_at(0, 2));
+        state.play(Move::Pass).unwrap();
+        state.play(Move::Play(1, 2)).unwrap();
+        let board = state.board();
+        assert_eq!(board.chain_id_at(1, 2), board.chain_id_at(0, 2));
     }
 
     #[test]
     fn test_check_for_capture() {
-        let mut test_board: Board = Board::new(3);
-
-        let mut black_player_model = PlayerModel::new(crate::game::BLACK);
-        let mut white_player_model = PlayerModel::new(crate::game::WHITE);
-
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 0, 0);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 0, 1);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 0, 2);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 1, 0);
-        game::place_stone(&mut test_board, &mut black_player_model, &mut white_player_model, 1, 1);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut black_player_model, &mut white_player_model, 1, 2);
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 2, 1);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 2, 2);
-        assert_eq!(test_board.board_state[1][1].player_color, WHITE_TERR);
-        assert_eq!(test_board.board_state[1][2].player_color, WHITE_TERR);
+        let mut state = GameState::new(3);
+        play_stones(&mut state, &[(1, 1), (1, 2)], &[(0, 0), (0, 1), (0, 2), (1, 0), (2, 1)]);
+        state.play(Move::Pass).unwrap();
+        state.play(Move::Play(2, 2)).unwrap();
+        let board = state.board();
+        assert!(board.point_state(1, 1).is_empty());
+        assert!(board.point_state(1, 2).is_empty());
+        assert_eq!(board.white_captured, 2);
+        assert_eq!(board.black_captured, 0);
     }
 
     #[test]
     fn test_check_for_capture_corner() {
-        let mut test_board: Board = Board::new(3);
+        let mut state = GameState::new(3);
 
-        let mut black_player_model = PlayerModel::new(crate::game::BLACK);
-        let mut white_player_model = PlayerModel::new(crate::game::WHITE);
-        test_board.toggle_turn();
+        // Place black stone in corner, white surrounds it with three stones
+        play_stones(&mut state, &[(0, 0)], &[(0, 1), (1, 1), (1, 0)]);
 
-        // Place black stone in corner
-        game::place_stone(&mut test_board, &mut black_player_model, &mut white_player_model, 0, 0);
-        
-        // White surrounds it with three stones
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 0, 1);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 1, 1);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 1, 0);
-        
-        // Black stone should be captured and replaced with white territory
-        assert_eq!(test_board.board_state[0][0].player_color, WHITE_TERR);
+        // Black stone should be captured and removed from the board
+        assert!(state.board().point_state(0, 0).is_empty());
+        assert_eq!(state.board().white_captured, 1);
     }
-    
+
     #[test]
     fn test_check_for_self_capture() {
-        let mut test_board: Board = Board::new(3);
-        let mut black_player_model = PlayerModel::new(crate::game::BLACK);
-        let mut white_player_model = PlayerModel::new(crate::game::WHITE);
-
         // Test basic self capture scenario
         // Set up a position where placing a stone would result in self capture
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 0, 1);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 1, 0);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 1, 2);
-        test_board.toggle_turn();
-        game::place_stone(&mut test_board, &mut white_player_model, &mut black_player_model, 2, 1);
+        let mut state = GameState::new(3);
+        play_stones(&mut state, &[], &[(0, 1), (1, 0), (1, 2), (2, 1)]);
 
         // Attempt to place black stone at (1,1) - should fail due to self capture
-        assert!(game::check_for_self_capture(&mut test_board, 1, 1));
+        assert_eq!(state.to_move(), Color::Black);
+        assert!(game::check_for_self_capture(&state.board, 1, 1));
 
         // Test non-self capture scenario
-        let mut test_board2: Board = Board::new(3);
-        
         // Set up a position where placing a stone would be legal
-        game::place_stone(&mut test_board2, &mut white_player_model, &mut black_player_model, 0, 0);
-        test_board2.toggle_turn();
-        game::place_stone(&mut test_board2, &mut white_player_model, &mut black_player_model, 0, 1);
+        let mut state2 = GameState::new(3);
+        play_stones(&mut state2, &[], &[(0, 0), (0, 1)]);
 
         // Attempt to place stone at (0, 2) - should be valid (not self capture)
-        assert!(!game::check_for_self_capture(&mut test_board2, 0, 2));
+        assert!(!game::check_for_self_capture(&state2.board, 0, 2));
 
         // Test corner self capture scenario
-        let mut test_board3: Board = Board::new(2);
-        
-        game::place_stone(&mut test_board3, &mut white_player_model, &mut black_player_model, 0, 1);
-        test_board3.toggle_turn();
-        game::place_stone(&mut test_board3, &mut white_player_model, &mut black_player_model, 1, 0);
+        let mut state3 = GameState::new(2);
+        play_stones(&mut state3, &[], &[(0, 1), (1, 0)]);
 
         // Attempt to place black stone at (0,0) - should fail due to self capture
-        assert!(game::check_for_self_capture(&mut test_board3, 0, 0));
+        assert!(game::check_for_self_capture(&state3.board, 0, 0));
     }
 
     #[test]
@@ -581,27 +1700,58 @@ mod tests {
         let board = Board::new(9);
         
         // Check board size
-        assert_eq!(board.board_size, 9);
+        assert_eq!((board.width, board.height), (9, 9));
         
-        // Check initial turn
-        assert!(board.is_white_turn);
+        // Check initial turn, black always moves first
+        assert!(!board.is_white_turn);
         
         // Check all intersections are empty
         for row in 0..9 {
             for col in 0..9 {
-                assert_eq!(board.board_state[row][col].get_player_color(), game::EMPTY);
+                assert!(board.point_state(row, col).is_empty());
             }
         }
     }
 
+    #[test]
+    fn test_star_points_match_handicap_points() {
+        for (size, count) in [(9, 5), (13, 5), (19, 9)] {
+            let mut stars = game::star_points(size, size);
+            let mut handicap = game::handicap_points(size, count).unwrap();
+            stars.sort();
+            handicap.sort();
+            assert_eq!(stars, handicap);
+        }
+        assert!(game::star_points(6, 19).is_empty());
+        assert_eq!(game::star_points(19, 10).len(), 6);
+        assert_eq!(game::star_points(25, 25).len(), 9);
+    }
+
+    #[test]
+    fn test_rectangular_board() {
+        let mut state = GameState::with_dimensions(7, 3, RuleSet::default());
+        assert_eq!(state.board().intersections().count(), 21);
+        assert!(!state.board().is_square());
+        assert!(!state.place_fixed_handicap(2));
+
+        // The far corner of the long side is on the board, one row further down is not
+        play_stones(&mut state, &[(1, 6), (0, 5)], &[(0, 6)]);
+        assert!(state.board().point_state(0, 6).is_empty());
+        assert_eq!(state.board().black_captured, 1);
+        assert_eq!(state.play(Move::Play(3, 0)), Err(MoveError::OutOfBounds));
+        assert_eq!(state.board().hash, state.board().compute_hash());
+    }
+
     #[test]
     fn test_player_model_initialization() {
-        let white_player = PlayerModel::new(WHITE);
-        let black_player = PlayerModel::new(BLACK);
-        
-        assert_eq!(white_player.get_player_color(), WHITE);
-        assert_eq!(black_player.get_player_color(), BLACK);
+        let white_player = PlayerModel::new(Color::White);
+        let black_player = PlayerModel::new(Color::Black);
         
+        assert_eq!(white_player.get_player_color(), Color::White);
+        assert_eq!(black_player.get_player_color(), Color::Black);
+        assert_eq!(white_player.player.opponent_color, Color::Black);
+        assert_eq!(black_player.player.opponent_color, Color::White);
+
         // Check chains and liberties are empty initially
         assert!(white_player.player_chains.is_empty());
         assert!(white_player.player_liberties.is_empty());
@@ -612,83 +1762,837 @@ mod tests {
     #[test]
     fn test_turn_toggling() {
         let mut board = Board::new(9);
-        assert!(board.is_white_turn);
-        
-        board.toggle_turn();
         assert!(!board.is_white_turn);
         
         board.toggle_turn();
         assert!(board.is_white_turn);
+        
+        board.toggle_turn();
+        assert!(!board.is_white_turn);
     }
 
     #[test]
     fn test_invalid_stone_placement() {
-        let mut board = Board::new(9);
-        let mut white_player = PlayerModel::new(WHITE);
-        let mut black_player = PlayerModel::new(BLACK);
+        let mut state = GameState::new(9);
 
         // Place stone successfully
-        assert!(place_stone(&mut board, &mut white_player, &mut black_player, 0, 0));
+        assert!(state.play(Move::Play(0, 0)).is_ok());
         
         // Try to place stone in occupied position
-        assert!(!place_stone(&mut board, &mut black_player, &mut white_player, 0, 0));
-        
-        // Try to place stone out of turn
-        assert!(!place_stone(&mut board, &mut white_player, &mut black_player, 0, 1));
+        assert_eq!(state.play(Move::Play(0, 0)), Err(MoveError::Occupied));
+
+        // Try to place stone off the board
+        assert_eq!(state.play(Move::Play(9, 0)), Err(MoveError::OutOfBounds));
+        assert_eq!(state.to_move(), Color::White);
+        assert_eq!(state.moves(), &[(Color::Black, Move::Play(0, 0))]);
     }
 
     #[test]
-    fn test_chain_formation() {
+    fn test_place_stone_out_of_turn() {
         let mut board = Board::new(9);
-        let mut white_player = PlayerModel::new(WHITE);
-        let mut black_player = PlayerModel::new(BLACK);
+        let mut white_player = PlayerModel::new(Color::White);
+        let mut black_player = PlayerModel::new(Color::Black);
+
+        // The low-level function still refuses the wrong player model
+        assert_eq!(game::place_stone(&mut board, &mut white_player, &mut black_player, 0, 0), Err(MoveError::WrongTurn));
+        assert!(game::place_stone(&mut board, &mut black_player, &mut white_player, 0, 0).is_ok());
+        assert_eq!(game::place_stone(&mut board, &mut black_player, &mut white_player, 0, 1), Err(MoveError::WrongTurn));
+    }
+
+    #[test]
+    fn test_game_state_alternates_turns() {
+        let mut state = GameState::new(9);
+        state.play(Move::Play(2, 2)).unwrap();
+        state.play(Move::Pass).unwrap();
+        state.play(Move::Play(2, 3)).unwrap();
+
+        // The two black stones were played by black and form one chain
+        assert_eq!(state.board().point_state(2, 3), PointState::Stone(Color::Black));
+        assert_eq!(state.board().chain_id_at(2, 2), state.board().chain_id_at(2, 3));
+        assert_eq!(state.to_move(), Color::White);
+        assert_eq!(state.moves(), &[
+            (Color::Black, Move::Play(2, 2)),
+            (Color::White, Move::Pass),
+            (Color::Black, Move::Play(2, 3)),
+        ]);
+
+        // Refused moves are not recorded
+        assert!(state.play(Move::Play(2, 2)).is_err());
+        assert_eq!(state.moves().len(), 3);
+    }
+
+    #[test]
+    fn test_chain_formation() {
+        let mut state = GameState::new(9);
 
         // Create a chain of three stones
-        place_stone(&mut board, &mut white_player, &mut black_player, 0, 0);
-        board.toggle_turn();
-        place_stone(&mut board, &mut white_player, &mut black_player, 0, 1);
-        board.toggle_turn();
-        place_stone(&mut board, &mut white_player, &mut black_player, 0, 2);
-        board.toggle_turn();
+        play_stones(&mut state, &[(0, 0), (0, 1), (0, 2)], &[]);
 
         // Check that all stones are in the same chain
-        let chain_id = board.board_state[0][0].chain_id.clone();
-        assert_eq!(board.board_state[0][1].chain_id, chain_id);
-        assert_eq!(board.board_state[0][2].chain_id, chain_id);
+        let board = state.board();
+        let chain_id = board.chain_id_at(0, 0);
+        assert_eq!(board.chain_id_at(0, 1), chain_id);
+        assert_eq!(board.chain_id_at(0, 2), chain_id);
         
         // Verify the chain exists in player_chains
-        assert!(white_player.player_chains.contains_key(&chain_id));
-        assert_eq!(white_player.player_chains.get(&chain_id).unwrap().len(), 3);
+        let black_player = state.player(Color::Black);
+        assert!(black_player.player_chains.contains_key(&chain_id));
+        assert_eq!(black_player.player_chains.get(&chain_id).unwrap().len(), 3);
     }
 
     #[test]
     fn test_multiple_captures() {
-        let mut board = Board::new(9);
-        let mut white_player = PlayerModel::new(WHITE);
-        let mut black_player = PlayerModel::new(BLACK);
+        let mut state = GameState::new(9);
 
         // Set up a position where multiple stones can be captured
-        // Black stones at (1,1) and (2,1)
-        board.toggle_turn();
-        place_stone(&mut board, &mut black_player, &mut white_player, 1, 1);
-        board.toggle_turn();
-        place_stone(&mut board, &mut black_player, &mut white_player, 2, 1);
-
-        // Surround with white stones
-        place_stone(&mut board, &mut white_player, &mut black_player, 0, 1);
-        board.toggle_turn();
-        place_stone(&mut board, &mut white_player, &mut black_player, 1, 0);
-        board.toggle_turn();
-        place_stone(&mut board, &mut white_player, &mut black_player, 1, 2);
-        board.toggle_turn();
-        place_stone(&mut board, &mut white_player, &mut black_player, 2, 0);
-        board.toggle_turn();
-        place_stone(&mut board, &mut white_player, &mut black_player, 2, 2);
-        board.toggle_turn();
-        place_stone(&mut board, &mut white_player, &mut black_player, 3, 1);
+        // Black stones at (1,1) and (2,1), surrounded with white stones
+        play_stones(&mut state, &[(1, 1), (2, 1)], &[(0, 1), (1, 0), (1, 2), (2, 0), (2, 2), (3, 1)]);
 
         // Check that both black stones were captured
-        assert_eq!(board.board_state[1][1].get_player_color(), WHITE_TERR);
-        assert_eq!(board.board_state[2][1].get_player_color(), WHITE_TERR);
+        let board = state.board();
+        assert!(board.point_state(1, 1).is_empty());
+        assert!(board.point_state(2, 1).is_empty());
+        assert_eq!(board.white_captured, 2);
+    }
+
+    #[test]
+    fn test_captured_point_can_be_replayed() {
+        let mut state = GameState::new(9);
+
+        // Black stone in the corner captured by two white stones
+        play_stones(&mut state, &[(0, 0), (5, 5)], &[(0, 1), (1, 0)]);
+        assert!(state.board().point_state(0, 0).is_empty());
+        assert_eq!(state.board().white_captured, 1);
+
+        // The emptied point is a normal intersection again
+        state.play(Move::Play(5, 6)).unwrap();
+        assert!(state.play(Move::Play(0, 0)).is_ok());
+        let board = state.board();
+        assert_eq!(board.point_state(0, 0), PointState::Stone(Color::White));
+        assert_eq!(board.chain_id_at(0, 0), board.chain_id_at(0, 1));
+    }
+
+    /**
+     * Build a position from a diagram of 'B', 'W' and '.' rows, top row first.
+     * The stones are played in turn, with passes when one color runs out.
+     * The diagram must not contain chains without liberties.
+     */
+    fn setup_position(diagram: &[&str], to_move: Color) -> GameState {
+        let mut state = GameState::new(diagram.len());
+        let mut black = Vec::new();
+        let mut white = Vec::new();
+        for (row, line) in diagram.iter().enumerate() {
+            for (col, point) in line.chars().enumerate() {
+                match point {
+                    'B' => black.push((row, col)),
+                    'W' => white.push((row, col)),
+                    _ => {}
+                }
+            }
+        }
+        play_stones(&mut state, &black, &white);
+        if state.to_move() != to_move {
+            state.play(Move::Pass).unwrap();
+        }
+        state
+    }
+
+    #[test]
+    fn test_capture_into_eye_is_legal() {
+        let mut state = setup_position(&[
+            ".WB..",
+            "WB...",
+            "B....",
+            ".....",
+            ".....",
+        ], Color::Black);
+
+        // (0,0) has no empty neighbours but playing there captures both white stones
+        assert!(!game::check_for_self_capture(&state.board, 0, 0));
+        let outcome = state.play(Move::Play(0, 0)).unwrap();
+        assert_eq!(outcome.chain, vec![(0, 0)]);
+        assert_eq!(outcome.captured.len(), 2);
+        assert!(outcome.captured.contains(&(0, 1)) && outcome.captured.contains(&(1, 0)));
+        let board = state.board();
+        assert!(board.point_state(0, 1).is_empty());
+        assert!(board.point_state(1, 0).is_empty());
+        assert_eq!(board.black_captured, 2);
+    }
+
+    #[test]
+    fn test_snapback() {
+        let mut state = setup_position(&[
+            "..BW.",
+            "BWW..",
+            ".....",
+            ".....",
+            ".....",
+        ], Color::White);
+
+        // White throws in, black captures the single stone
+        assert!(state.play(Move::Play(0, 0)).is_ok());
+        assert!(state.play(Move::Play(0, 1)).is_ok());
+        assert!(state.board().point_state(0, 0).is_empty());
+        assert_eq!(state.board().black_captured, 1);
+
+        // White plays back into the surrounded point and captures the whole black chain
+        assert!(state.play(Move::Play(0, 0)).is_ok());
+        let board = state.board();
+        assert!(board.point_state(0, 1).is_empty());
+        assert!(board.point_state(0, 2).is_empty());
+        assert_eq!(board.point_state(1, 0), PointState::Stone(Color::Black));
+        assert_eq!(board.white_captured, 2);
+    }
+
+    #[test]
+    fn test_multi_stone_suicide_is_rejected() {
+        let mut state = setup_position(&[
+            ".BW..",
+            "BBW..",
+            "WW...",
+            ".....",
+            ".....",
+        ], Color::Black);
+
+        // Filling the last liberty of the merged black chain captures nothing
+        assert!(game::check_for_self_capture(&state.board, 0, 0));
+        assert_eq!(state.play(Move::Play(0, 0)), Err(MoveError::Suicide));
+        assert!(state.board().point_state(0, 0).is_empty());
+
+        // The same point is a capture for white
+        state.play(Move::Pass).unwrap();
+        assert!(state.play(Move::Play(0, 0)).is_ok());
+        assert_eq!(state.board().white_captured, 3);
+        assert!(state.board().point_state(1, 1).is_empty());
+    }
+
+    #[test]
+    fn test_merged_chain_liberties_count() {
+        let mut state = setup_position(&[
+            ".BBB.",
+            "BWWW.",
+            "W....",
+            ".....",
+            ".....",
+        ], Color::Black);
+
+        // Neither direct neighbour has a liberty, but the merged chain still reaches (0,4)
+        assert!(!game::check_for_self_capture(&state.board, 0, 0));
+        assert!(state.play(Move::Play(0, 0)).is_ok());
+        let board = state.board();
+        assert_eq!(board.point_state(0, 0), PointState::Stone(Color::Black));
+        assert_eq!(board.chain_id_at(1, 0), board.chain_id_at(0, 3));
+    }
+
+    #[test]
+    fn test_single_stone_suicide_in_eye() {
+        let mut state = setup_position(&[
+            ".W...",
+            "W....",
+            ".....",
+            ".....",
+            ".....",
+        ], Color::Black);
+
+        assert!(game::check_for_self_capture(&state.board, 0, 0));
+        assert_eq!(state.play(Move::Play(0, 0)), Err(MoveError::Suicide));
+        assert_eq!(state.to_move(), Color::Black);
+    }
+
+    const KO_SHAPE: [&str; 5] = [
+        ".BW..",
+        "BW.W.",
+        ".BW..",
+        ".....",
+        ".....",
+    ];
+
+    #[test]
+    fn test_simple_ko_recapture_rejected() {
+        let mut state = setup_position(&KO_SHAPE, Color::Black);
+
+        // Black takes the ko
+        assert!(state.play(Move::Play(1, 2)).is_ok());
+        assert!(state.board().point_state(1, 1).is_empty());
+        assert_eq!(state.board().ko_point, Some((1, 1)));
+
+        // White cannot retake straight away
+        assert_eq!(game::check_ko(&state.board, 1, 1), Some(KoViolation::SimpleKo));
+        assert_eq!(state.check_play(1, 1), Err(MoveError::Ko(KoViolation::SimpleKo)));
+        assert_eq!(state.play(Move::Play(1, 1)), Err(MoveError::Ko(KoViolation::SimpleKo)));
+        assert_eq!(state.to_move(), Color::White);
+
+        // After an exchange elsewhere the ko can be retaken
+        assert!(state.play(Move::Play(4, 4)).is_ok());
+        assert_eq!(state.board().ko_point, None);
+        assert!(state.play(Move::Play(4, 0)).is_ok());
+        assert!(state.play(Move::Play(1, 1)).is_ok());
+        assert!(state.board().point_state(1, 2).is_empty());
+        assert_eq!(state.board().ko_point, Some((1, 2)));
+    }
+
+    #[test]
+    fn test_snapback_is_not_ko() {
+        let mut state = setup_position(&[
+            "..BW.",
+            "BWW..",
+            ".....",
+            ".....",
+            ".....",
+        ], Color::White);
+        state.play(Move::Play(0, 0)).unwrap();
+        state.play(Move::Play(0, 1)).unwrap();
+
+        // The capturing stone joined a chain, so no ko point is set
+        assert_eq!(state.board().ko_point, None);
+        assert_eq!(game::check_ko(&state.board, 0, 0), None);
+    }
+
+    #[test]
+    fn test_superko_repeated_position() {
+        let mut state = setup_position(&KO_SHAPE, Color::Black);
+        state.board.rules.ko_rule = KoRule::PositionalSuperko;
+        state.play(Move::Play(1, 2)).unwrap();
+
+        // Without the simple ko point, retaking still recreates the position before black took the ko
+        state.board.ko_point = None;
+        assert_eq!(game::check_ko(&state.board, 1, 1), Some(KoViolation::PositionalSuperko));
+
+        // That earlier position also had black to move, so situational superko refuses it too
+        state.board.rules.ko_rule = KoRule::SituationalSuperko;
+        assert_eq!(game::check_ko(&state.board, 1, 1), Some(KoViolation::SituationalSuperko));
+        assert_eq!(state.play(Move::Play(1, 1)), Err(MoveError::Ko(KoViolation::SituationalSuperko)));
+
+        // A position never seen before is fine
+        assert_eq!(game::check_ko(&state.board, 4, 4), None);
+        assert!(state.play(Move::Play(4, 4)).is_ok());
+    }
+
+    #[test]
+    fn test_two_passes_end_play() {
+        let mut state = GameState::new(9);
+
+        assert!(state.play(Move::Pass).is_ok());
+        assert_eq!(state.to_move(), Color::White);
+        assert_eq!(state.board().phase, GamePhase::Playing);
+
+        // A stone in between resets the pass count
+        assert!(state.play(Move::Play(4, 4)).is_ok());
+        assert_eq!(state.board().consecutive_passes, 0);
+        assert!(state.play(Move::Pass).is_ok());
+        assert!(state.play(Move::Pass).is_ok());
+        assert_eq!(state.board().phase, GamePhase::Scoring);
+
+        // No more moves once play has ended
+        assert_eq!(state.play(Move::Pass), Err(MoveError::GameOver));
+        assert_eq!(state.play(Move::Play(0, 0)), Err(MoveError::GameOver));
+        assert!(state.board().point_state(0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_pass_clears_ko() {
+        let mut state = setup_position(&KO_SHAPE, Color::Black);
+        state.play(Move::Play(1, 2)).unwrap();
+        assert!(state.play(Move::Pass).is_ok());
+        assert_eq!(state.board().ko_point, None);
+    }
+
+    #[test]
+    fn test_resign() {
+        let mut state = GameState::new(9);
+        assert!(state.play(Move::Resign).is_ok());
+        let board = state.board();
+        assert_eq!(board.phase, GamePhase::Finished);
+        assert_eq!(board.result, Some(GameResult::Resignation { winner: Color::White }));
+        assert_eq!(board.result.unwrap().to_string(), "W+R");
+
+        // The game is over, nothing else is accepted
+        assert_eq!(state.play(Move::Resign), Err(MoveError::GameOver));
+        assert_eq!(state.play(Move::Pass), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn test_handicap_points() {
+        assert_eq!(game::handicap_points(19, 2), Some(vec![(15, 3), (3, 15)]));
+        assert_eq!(game::handicap_points(9, 3), Some(vec![(6, 2), (2, 6), (2, 2)]));
+        assert_eq!(game::handicap_points(13, 5).unwrap().last(), Some(&(6, 6)));
+        assert_eq!(game::handicap_points(19, 6).unwrap().len(), 6);
+        assert!(!game::handicap_points(19, 6).unwrap().contains(&(9, 9)));
+        assert_eq!(game::handicap_points(19, 7).unwrap().len(), 7);
+        assert_eq!(game::handicap_points(19, 9).unwrap().len(), 9);
+        assert_eq!(game::handicap_points(19, 1), None);
+        assert_eq!(game::handicap_points(19, 10), None);
+        assert_eq!(game::handicap_points(7, 2), None);
+    }
+
+    #[test]
+    fn test_fixed_handicap_gives_white_the_move() {
+        let mut state = GameState::new(9);
+
+        assert!(state.place_fixed_handicap(4));
+        assert_eq!(state.board().handicap, 4);
+        assert_eq!(state.to_move(), Color::White);
+        for (row, col) in game::handicap_points(9, 4).unwrap() {
+            assert_eq!(state.board().point_state(row, col), PointState::Stone(Color::Black));
+        }
+        assert_eq!(state.board().position_history.len(), 1);
+        assert!(state.moves().is_empty());
+
+        // Handicap can only be given once, before play starts
+        assert!(!state.place_fixed_handicap(2));
+        assert!(state.play(Move::Play(4, 4)).is_ok());
+    }
+
+    #[test]
+    fn test_free_handicap() {
+        let mut state = GameState::new(9);
+
+        // Repeated or off-board points are refused
+        assert!(!state.place_handicap(&[(0, 0), (0, 0)]));
+        assert!(!state.place_handicap(&[(0, 0), (9, 0)]));
+
+        // Adjacent stones are allowed and form a chain
+        assert!(state.place_handicap(&[(4, 4), (4, 5), (0, 8)]));
+        assert_eq!(state.board().handicap, 3);
+        assert_eq!(state.to_move(), Color::White);
+        assert_eq!(state.board().chain_id_at(4, 4), state.board().chain_id_at(4, 5));
+    }
+
+    #[test]
+    fn test_refused_handicap_leaves_board_unchanged() {
+        // Filling the whole board leaves black without liberties, refused whether or not suicide is allowed
+        for rules in [RuleSet::chinese(), RuleSet::new_zealand()] {
+            let mut state = GameState::with_rules(2, rules);
+            assert!(!state.place_handicap(&[(0, 0), (0, 1), (1, 0), (1, 1)]));
+            assert_eq!(state.board().handicap, 0);
+            assert!(state.board().stones().is_empty());
+            assert_eq!(state.board().hash, state.board().compute_hash());
+            assert_eq!(state.to_move(), Color::Black);
+            assert!(!state.board().has_started());
+
+            // The same game still takes a handicap that leaves black a liberty
+            assert!(state.place_handicap(&[(0, 0), (1, 1)]));
+            assert_eq!(state.board().handicap, 2);
+            assert_eq!(state.to_move(), Color::White);
+        }
+    }
+
+    #[test]
+    fn test_suicide_allowed_removes_own_chain() {
+        let mut state = setup_position(&[
+            ".BW..",
+            "BBW..",
+            "WW...",
+            ".....",
+            ".....",
+        ], Color::Black);
+        state.board.rules = RuleSet::new_zealand();
+
+        // The merged black chain comes off the board and white gets the prisoners
+        let outcome = state.play(Move::Play(0, 0)).unwrap();
+        assert_eq!(outcome.suicide.len(), 4);
+        assert!(outcome.captured.is_empty() && outcome.chain.is_empty());
+        for (row, col) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            assert!(state.board().point_state(row, col).is_empty());
+        }
+        assert_eq!(state.board().white_captured, 4);
+        assert_eq!(state.to_move(), Color::White);
+
+        // The emptied points can be played again
+        assert!(state.play(Move::Play(1, 1)).is_ok());
+    }
+
+    #[test]
+    fn test_single_stone_suicide_repeats_position() {
+        let mut state = setup_position(&[
+            ".W...",
+            "W....",
+            ".....",
+            ".....",
+            ".....",
+        ], Color::Black);
+        state.board.rules = RuleSet::tromp_taylor();
+
+        // Removing the only stone played leaves the board as it was
+        assert_eq!(state.play(Move::Play(0, 0)), Err(MoveError::Ko(KoViolation::PositionalSuperko)));
+        assert!(state.board().point_state(0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_aga_pass_stones_and_white_passes_last() {
+        let mut state = GameState::with_rules(9, RuleSet::aga());
+        state.play(Move::Play(4, 4)).unwrap();
+        state.play(Move::Pass).unwrap();
+        state.play(Move::Pass).unwrap();
+
+        // Black passed last, so white has to pass again
+        assert_eq!(state.board().phase, GamePhase::Playing);
+        state.play(Move::Pass).unwrap();
+        assert_eq!(state.board().phase, GamePhase::Scoring);
+        assert_eq!(state.board().black_captured, 2);
+        assert_eq!(state.board().white_captured, 1);
+    }
+
+    #[test]
+    fn test_rule_set_presets() {
+        let japanese = RuleSet::japanese();
+        assert_eq!(japanese.ko_rule, KoRule::Simple);
+        assert_eq!(japanese.scoring_method, game::ScoringMethod::Territory);
+        assert!(!RuleSet::chinese().suicide_allowed);
+        assert!(RuleSet::new_zealand().suicide_allowed);
+        assert!(RuleSet::aga().pass_stones);
+        assert_eq!(RuleSet::default(), RuleSet::chinese());
+
+        // Only pass stones rules keep play going after black passes last
+        let mut state = GameState::with_rules(9, japanese);
+        state.play(Move::Play(4, 4)).unwrap();
+        state.play(Move::Pass).unwrap();
+        state.play(Move::Pass).unwrap();
+        assert_eq!(state.board().phase, GamePhase::Scoring);
+        assert_eq!(state.board().black_captured + state.board().white_captured, 0);
+    }
+
+    #[test]
+    fn test_handicap_rules() {
+        // Handicap compensation differs between rule sets and replaces the even-game komi
+        let mut chinese = GameState::with_rules(9, RuleSet::chinese());
+        assert_eq!(chinese.board().white_compensation(), 7.5);
+        assert!(chinese.place_fixed_handicap(4));
+        assert_eq!(chinese.board().komi(), 0.5);
+        assert_eq!(chinese.board().white_compensation(), 4.5);
+        let mut aga = GameState::with_rules(9, RuleSet::aga());
+        assert!(aga.place_fixed_handicap(4));
+        assert_eq!(aga.board().white_compensation(), 3.5);
+        let mut japanese = GameState::with_rules(9, RuleSet::japanese());
+        assert!(japanese.place_fixed_handicap(4));
+        assert_eq!(japanese.board().white_compensation(), 0.5);
+
+        // Free placement is refused where the rules use fixed handicap
+        let mut fixed_only = GameState::with_rules(9, RuleSet::japanese());
+        assert!(!fixed_only.place_handicap(&[(0, 0), (8, 8)]));
+        assert_eq!(fixed_only.board().handicap, 0);
+    }
+
+    /**
+     * Check every chain kept on the player models against a flood fill of the board
+     */
+    fn assert_chains_match_board(state: &mut GameState) {
+        for (row, col) in state.board.intersections() {
+            let color = match state.board.point_state(row, col).stone() {
+                Some(color) => color,
+                None => continue,
+            };
+            let chain_id = state.board.chain_id_at(row, col);
+            let mut chain = game::collect_chain(&state.board, row, col);
+            let liberties: HashSet<(usize, usize)> = chain.iter()
+                .flat_map(|&(stone_row, stone_col)| game::get_adjacent(&state.board, stone_row, stone_col, PointState::Empty))
+                .collect();
+            let player = state.player(color);
+            let mut tracked = player.player_chains.get(&chain_id).cloned().unwrap();
+            chain.sort();
+            tracked.sort();
+            assert_eq!(tracked, chain);
+            assert_eq!(player.get_liberties(chain_id), Some(&liberties));
+        }
+    }
+
+    #[test]
+    fn test_chains_match_board_in_random_games() {
+        let mut rng = TestRng::new(12345);
+        for rules in [RuleSet::chinese(), RuleSet::new_zealand()] {
+            let mut state = GameState::with_rules(9, rules);
+            for _ in 0..1000 {
+                let point = rng.below(81);
+                let (row, col) = (point / 9, point % 9);
+                if state.board.point_state(row, col).is_empty() {
+                    let (player_model, opponent_model) = if state.to_move() == Color::White {
+                        (&state.white, &state.black)
+                    } else {
+                        (&state.black, &state.white)
+                    };
+                    let suicide = game::is_suicide(&state.board, player_model, opponent_model, row, col);
+                    assert_eq!(suicide, game::check_for_self_capture(&state.board, row, col));
+                }
+                if state.play(Move::Play(row, col)).is_ok() {
+                    assert_chains_match_board(&mut state);
+                    assert_eq!(state.board.hash, state.board.compute_hash());
+                }
+            }
+            assert!(state.moves().len() > 50);
+        }
+    }
+
+    /**
+     * Plays pseudo-random 19x19 games and prints the time per move, then the time to decide
+     * whether a stone would be suicide on every empty point of each position, once with the
+     * liberty sets kept on the player models and once with the flood fill rescan moves used before
+     * Run with cargo test --release bench_random_19x19_games -- --ignored --nocapture
+     */
+    #[test]
+    #[ignore]
+    fn bench_random_19x19_games() {
+        for rules in [RuleSet::japanese(), RuleSet::chinese()] {
+            let mut rng = TestRng::new(2024);
+            let (mut moves, mut checks) = (0, 0);
+            let mut playing = std::time::Duration::ZERO;
+            let (mut kept, mut rescanned) = (std::time::Duration::ZERO, std::time::Duration::ZERO);
+            for _ in 0..10 {
+                let mut state = GameState::with_rules(19, rules);
+                for _ in 0..2000 {
+                    let empty: Vec<(usize, usize)> = state.board.intersections()
+                        .filter(|&(row, col)| state.board.point_state(row, col).is_empty())
+                        .collect();
+                    let (player_model, opponent_model) = match state.to_move() {
+                        Color::White => (&state.white, &state.black),
+                        Color::Black => (&state.black, &state.white),
+                    };
+                    let start = std::time::Instant::now();
+                    let kept_suicides = empty.iter()
+                        .filter(|&&(row, col)| game::is_suicide(&state.board, player_model, opponent_model, row, col))
+                        .count();
+                    kept += start.elapsed();
+                    let start = std::time::Instant::now();
+                    let rescanned_suicides = empty.iter()
+                        .filter(|&&(row, col)| game::check_for_self_capture(&state.board, row, col))
+                        .count();
+                    rescanned += start.elapsed();
+                    assert_eq!(kept_suicides, rescanned_suicides);
+                    checks += empty.len() as u32;
+
+                    let point = rng.below(361);
+                    let start = std::time::Instant::now();
+                    let played = state.play(Move::Play(point / 19, point % 19));
+                    playing += start.elapsed();
+                    if played.is_ok() {
+                        moves += 1;
+                    }
+                }
+            }
+            eprintln!(
+                "{}: {moves} moves, {:?} per move. Suicide check on {checks} points: {:?} per point from the liberty sets, \
+                 {:?} per point rescanning chains, {:.1}x faster",
+                rules.name,
+                playing / moves,
+                kept / checks,
+                rescanned / checks,
+                rescanned.as_secs_f64() / kept.as_secs_f64(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_zobrist_hash_transpositions() {
+        let mut first = GameState::new(9);
+        play_stones(&mut first, &[(2, 2), (6, 6)], &[(2, 6), (6, 2)]);
+        let mut second = GameState::new(9);
+        play_stones(&mut second, &[(6, 6), (2, 2)], &[(6, 2), (2, 6)]);
+
+        // The same stones give the same hash whatever the move order
+        assert_ne!(first.board().hash, 0);
+        assert_eq!(first.board().hash, second.board().hash);
+        assert_eq!(first.board().situational_hash(), second.board().situational_hash());
+
+        // Passing changes the side to move but not the stones
+        second.play(Move::Pass).unwrap();
+        assert_eq!(first.board().hash, second.board().hash);
+        assert_ne!(first.board().situational_hash(), second.board().situational_hash());
+        assert_eq!(Board::new(9).hash, 0);
+    }
+
+    #[test]
+    fn test_zobrist_hash_after_capture_and_ko() {
+        let mut state = setup_position(&KO_SHAPE, Color::Black);
+        let before_ko = state.board().hash;
+        assert_eq!(state.board().full_hash(), state.board().situational_hash());
+
+        // Taking the ko removes a white stone from the hash and adds the ko point
+        state.play(Move::Play(1, 2)).unwrap();
+        assert_eq!(state.board().hash, state.board().compute_hash());
+        assert_ne!(state.board().hash, before_ko);
+        assert_ne!(state.board().full_hash(), state.board().situational_hash());
+
+        // A pass lifts the ko restriction, so only the side to move changes
+        let stones = state.board().hash;
+        state.play(Move::Pass).unwrap();
+        assert_eq!(state.board().hash, stones);
+        assert_eq!(state.board().full_hash(), state.board().situational_hash());
+        assert_eq!(state.board().position_history.last(), Some(&(stones, true)));
+    }
+
+    #[test]
+    fn test_legal_moves_match_play() {
+        let mut rng = TestRng::new(99);
+        let mut state = GameState::new(7);
+        for _ in 0..60 {
+            // Every listed move can be played on a copy of the game, and no other point can
+            let legal_moves = state.legal_moves();
+            for row in 0..7 {
+                for col in 0..7 {
+                    let mut copy = state.clone();
+                    let played = copy.play(Move::Play(row, col));
+                    assert_eq!(played.is_ok(), legal_moves.contains(&(row, col)));
+                    assert_eq!(played.err(), state.check_play(row, col).err());
+                }
+            }
+            if legal_moves.is_empty() {
+                break;
+            }
+            let (row, col) = legal_moves[rng.below(legal_moves.len())];
+            state.play(Move::Play(row, col)).unwrap();
+        }
+
+        // The copies never touched the original game
+        assert!(state.moves().len() > 20);
+        assert_eq!(state.board().hash, state.board().compute_hash());
+    }
+
+    #[test]
+    fn test_largest_board() {
+        let mut state = GameState::new(game::MAX_BOARD_SIZE);
+        assert_eq!(state.legal_moves().len(), 25 * 25);
+        play_stones(&mut state, &[(24, 24)], &[(24, 23), (23, 24)]);
+        assert!(state.board().point_state(24, 24).is_empty());
+        assert_eq!(state.board().white_captured, 1);
+        assert_eq!(state.board().hash, state.board().compute_hash());
+    }
+
+    #[test]
+    fn test_play_on_after_passes() {
+        let mut state = GameState::new(9);
+        play_stones(&mut state, &[(2, 2)], &[(6, 6)]);
+        state.play(Move::Pass).unwrap();
+        state.play(Move::Pass).unwrap();
+        assert_eq!(state.board().phase, GamePhase::Scoring);
+        assert!(state.toggle_chain_status(6, 6));
+
+        // play keeps the game in scoring, play_on resumes it and drops the marked stones
+        assert_eq!(state.play(Move::Play(4, 4)), Err(MoveError::GameOver));
+        assert_eq!(state.play_on(Move::Play(6, 6)), Err(MoveError::Occupied));
+        assert_eq!(state.board().phase, GamePhase::Scoring);
+        assert!(!state.board().chain_status.is_empty());
+        assert!(state.play_on(Move::Play(4, 4)).is_ok());
+        assert_eq!(state.board().phase, GamePhase::Playing);
+        assert_eq!(state.board().consecutive_passes, 0);
+        assert!(state.board().chain_status.is_empty());
+
+        // Undo goes back to scoring, and two more passes end play again
+        assert!(state.undo());
+        assert_eq!(state.board().phase, GamePhase::Scoring);
+        assert_eq!(state.board().consecutive_passes, 2);
+        assert!(state.redo());
+        state.play(Move::Pass).unwrap();
+        assert_eq!(state.board().phase, GamePhase::Playing);
+        state.play(Move::Pass).unwrap();
+        assert_eq!(state.board().phase, GamePhase::Scoring);
+    }
+
+    #[test]
+    fn test_undo_and_redo_capture() {
+        let mut state = GameState::new(9);
+        play_stones(&mut state, &[(0, 0), (5, 5)], &[(0, 1)]);
+        let before_capture = state.board().hash;
+
+        // White captures the corner stone, then takes it back
+        state.play(Move::Play(1, 0)).unwrap();
+        assert!(state.board().point_state(0, 0).is_empty());
+        assert_eq!(state.board().white_captured, 1);
+        let after_capture = state.board().hash;
+        assert!(state.undo());
+        assert_eq!(state.board().point_state(0, 0), PointState::Stone(Color::Black));
+        assert!(state.board().point_state(1, 0).is_empty());
+        assert_eq!(state.board().white_captured, 0);
+        assert_eq!(state.board().hash, before_capture);
+        assert_eq!(state.to_move(), Color::White);
+        assert_chains_match_board(&mut state);
+
+        // Redo plays the same capture again
+        assert!(state.redo());
+        assert_eq!(state.board().hash, after_capture);
+        assert_eq!(state.board().white_captured, 1);
+        assert!(!state.redo());
+        assert_chains_match_board(&mut state);
+
+        // A move that cannot be played stays on the redo list with the ones below it
+        assert!(state.undo());
+        state.redo_moves.push(Move::Play(0, 1));
+        assert!(!state.redo());
+        assert_eq!(state.redo_moves, vec![Move::Play(1, 0), Move::Play(0, 1)]);
+        assert_eq!(state.board().hash, before_capture);
+        state.redo_moves.pop();
+        assert!(state.redo());
+        assert_eq!(state.board().hash, after_capture);
+    }
+
+    #[test]
+    fn test_undo_restores_ko_and_phase() {
+        let mut state = setup_position(&KO_SHAPE, Color::Black);
+        state.play(Move::Play(1, 2)).unwrap();
+        state.play(Move::Pass).unwrap();
+        assert_eq!(state.board().ko_point, None);
+
+        // Taking back the pass brings back the ko
+        assert!(state.undo());
+        assert_eq!(state.board().ko_point, Some((1, 1)));
+        assert_eq!(state.play(Move::Play(1, 1)), Err(MoveError::Ko(KoViolation::SimpleKo)));
+
+        // Taking back the second pass returns from scoring to play
+        state.play(Move::Pass).unwrap();
+        state.play(Move::Pass).unwrap();
+        assert_eq!(state.board().phase, GamePhase::Scoring);
+        assert!(state.undo());
+        assert_eq!(state.board().phase, GamePhase::Playing);
+        assert_eq!(state.board().consecutive_passes, 1);
+        assert_eq!(state.to_move(), Color::Black);
+
+        // A new move drops what could have been redone
+        state.play(Move::Play(4, 4)).unwrap();
+        assert!(!state.redo());
+    }
+
+    #[test]
+    fn test_undo_suicide() {
+        let mut state = setup_position(&[
+            ".BW..",
+            "BBW..",
+            "WW...",
+            ".....",
+            ".....",
+        ], Color::Black);
+        state.board.rules = RuleSet::new_zealand();
+        let before = state.board().hash;
+        state.play(Move::Play(0, 0)).unwrap();
+        assert!(state.undo());
+        assert_eq!(state.board().hash, before);
+        assert!(state.board().point_state(0, 0).is_empty());
+        assert_eq!(state.board().point_state(1, 1), PointState::Stone(Color::Black));
+        assert_eq!(state.board().white_captured, 0);
+        assert_chains_match_board(&mut state);
+    }
+
+    #[test]
+    fn test_undo_whole_random_game() {
+        let mut rng = TestRng::new(7);
+        let mut state = GameState::new(9);
+        let mut hashes = vec![state.board().hash];
+        for _ in 0..400 {
+            let point = rng.below(81);
+            if state.play(Move::Play(point / 9, point % 9)).is_ok() {
+                hashes.push(state.board().hash);
+            }
+        }
+        let final_hash = state.board().hash;
+        let move_count = state.moves().len();
+
+        // Step all the way back, then all the way forward again
+        while state.undo() {
+            hashes.pop();
+            assert_eq!(Some(&state.board().hash), hashes.last());
+            assert_chains_match_board(&mut state);
+        }
+        assert!(state.moves().is_empty());
+        while state.redo() {}
+        assert_eq!(state.moves().len(), move_count);
+        assert_eq!(state.board().hash, final_hash);
+        assert_chains_match_board(&mut state);
     }
 }
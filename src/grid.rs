@@ -1,8 +1,31 @@
-use crate::game::{self, Board, Player, PlayerModel};
+use std::time::Duration;
+
+use go::coordinates::CoordinateStyle;
+use go::engine::{EngineGame, EngineWorker};
+use go::game::{self, ChainStatus, GamePhase, GameState, Move, MoveError, PointState, RuleSet, TerritoryOwner};
+use go::scoring;
+use go::sgf;
+use go::tree::GameTree;
 use bevy::color::palettes::css::*;
 use bevy::prelude::*;
 
-pub const BOARD_SIZE: usize = 9;
+//Board a new window starts with unless --size says otherwise
+pub const DEFAULT_BOARD_SIZE: usize = 9;
+
+//Sizes the Size button steps through
+const BOARD_SIZES: [usize; 3] = [9, 13, 19];
+
+//Length of the board's longer side in pixels, the shorter side is scaled to keep the grid square
+const BOARD_PIXELS: f32 = 600.0;
+
+//Where Ctrl+S writes the game record and Ctrl+O reads it from
+const SAVE_PATH: &str = "game.sgf";
+
+//How long the engine may take over a single command, genmove included
+const ENGINE_TIMEOUT: Duration = Duration::from_secs(60);
+
+//How long a refused point flashes and its tooltip stays up
+const REJECTION_SECONDS: f32 = 1.2;
 
 
 
@@ -17,41 +40,102 @@ pub struct GridSquare {
 
 /**
  * Plugin to handle game initialization and grid interactions
+ * engine_command is the command line of a GTP engine to play against, from --engine
+ * board_size is the (columns, rows) of the first game, from --size
  */
-pub struct GridPlugin;
+pub struct GridPlugin {
+    pub engine_command: Option<String>,
+    pub board_size: (usize, usize),
+}
 
 impl Plugin for GridPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, create_gameboard)
+        let (width, height) = self.board_size;
+        app.insert_resource(EngineOpponent::new(self.engine_command.clone()))
+            .insert_resource(BoardLayout { width, height, labels: CoordinateStyle::default(), drawn: None })
+            .add_systems(Startup, create_gameboard)
             .add_systems(Startup, spawn_layout)
-            .add_systems(Update, grid_button_interaction);
+            .add_systems(Update, rebuild_board)
+            .add_systems(Update, relabel_board)
+            .add_systems(Update, grid_button_interaction)
+            .add_systems(Update, clear_rejections)
+            .add_systems(Update, control_button_interaction)
+            .add_systems(Update, navigation_keys)
+            .add_systems(Update, save_and_load_keys)
+            .add_systems(Update, engine_turn)
+            .add_systems(Update, update_game_status)
+            .add_systems(Update, draw_territory);
     }
 }
 
 /**
- * Creates the initial game board and player models.
- * Spawns the board and both players (black and white) into the game world.
+ * The game shown on the board, the engine's GameTree wrapped as a Bevy component
+ */
+#[derive(Component, Deref, DerefMut)]
+struct Game(GameTree);
+
+/**
+ * The size of the board on screen
+ * width and height are the size the first game starts with, drawn is the size the
+ * lines and intersections are currently laid out for, None before the first layout.
+ * labels is the style of the coordinates along the edges.
+ */
+#[derive(Resource)]
+struct BoardLayout {
+    width: usize,
+    height: usize,
+    labels: CoordinateStyle,
+    drawn: Option<(usize, usize)>,
+}
+
+/**
+ * The external GTP engine the player can play against
+ * color is the side the engine plays, None while the players move for both sides.
+ * requested holds the game the engine is thinking about, so an answer for a position
+ * the players have since moved away from is dropped.
  */
-fn create_gameboard(mut commands: Commands) {
-    let player_white_model = PlayerModel::new(game::WHITE);
-    let player_black_model = PlayerModel::new(game::BLACK);
-    let game_board: Board = Board::new(BOARD_SIZE);
-    commands.spawn((player_white_model, Player { player_color: game::WHITE, opponent_color: game::BLACK }));
-    commands.spawn((player_black_model, Player { player_color: game::BLACK, opponent_color: game::WHITE }));
-    commands.spawn(game_board);
+#[derive(Resource)]
+struct EngineOpponent {
+    command: Option<String>,
+    color: Option<game::Color>,
+    worker: Option<EngineWorker>,
+    requested: Option<EngineGame>,
+}
+
+impl EngineOpponent {
+    fn new(command: Option<String>) -> Self {
+        EngineOpponent {
+            command,
+            color: None,
+            worker: None,
+            requested: None,
+        }
+    }
+
+    //Whether the players must wait for the engine to move
+    fn is_thinking(&self, tree: &GameTree) -> bool {
+        self.color == Some(tree.state().to_move()) && tree.state().board().phase == GamePhase::Playing
+    }
+}
+
+/**
+ * Creates the initial game tree.
+ * The tree holds the game state, which owns the board and both players (black and white).
+ */
+fn create_gameboard(mut commands: Commands, layout: Res<BoardLayout>) {
+    let state = GameState::with_dimensions(layout.width, layout.height, RuleSet::default());
+    commands.spawn(Game(GameTree::new(state)));
 }
 
 /**
  * Creates the visual layout of the game board.
- * Spawns a camera and creates a grid of interactive squares representing the Go board.
+ * Spawns a camera, the text and buttons around the board and an empty wooden board.
+ * The lines and intersections are added by rebuild_board once the game's size is known.
  * The board is centered on screen with a dark background.
  */
 fn spawn_layout(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
 
-    let rows = BOARD_SIZE;
-    let cols = BOARD_SIZE;
-
     commands
         .spawn(NodeBundle {
             style: Style {
@@ -79,81 +163,300 @@ fn spawn_layout(mut commands: Commands) {
             // Turn indicator text
             parent.spawn((
                 TextBundle::from_section(
-                    "White's Turn",
+                    "Black's Turn",
                     TextStyle {
                         font_size: 32.0,
-                        color: Color::WHITE,
+                        color: Color::BLACK,
                         ..default()
                     },
                 ),
                 TurnText,
             ));
 
+            // Explains why the last move was refused
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 20.0,
+                        color: Color::srgb(1.0, 0.4, 0.4),
+                        ..default()
+                    },
+                ),
+                StatusText,
+            ));
+
+            // Shown once the game has ended
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 40.0,
+                        color: Color::srgb(1.0, 0.84, 0.0),
+                        ..default()
+                    },
+                ),
+                GameOverText,
+            ));
+
+            // Move number, whether this is the main line, and the node's comment
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 20.0,
+                        color: Color::srgb(0.85, 0.85, 0.85),
+                        ..default()
+                    },
+                ),
+                VariationText,
+            ));
+
             // Game board container
-            parent
-                .spawn(NodeBundle {
+            parent.spawn((
+                NodeBundle {
                     style: Style {
-                        width: Val::Px(600.0),
-                        height: Val::Px(600.0),
                         position_type: PositionType::Relative,
                         padding: UiRect::all(Val::Px(0.0)),
-                        margin: UiRect::top(Val::Px(20.0)),
+                        // Room for the coordinate labels outside the board
+                        margin: UiRect::vertical(Val::Px(40.0)),
                         ..default()
                     },
                     background_color: Color::srgb(0.87, 0.68, 0.34).into(), // Wooden board color
                     ..default()
+                },
+                BoardArea,
+            ));
+
+            // Pass, resign and end-of-scoring controls
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        column_gap: Val::Px(20.0),
+                        margin: UiRect::top(Val::Px(30.0)),
+                        ..default()
+                    },
+                    ..default()
                 })
                 .with_children(|parent| {
-                    // Grid lines
-                    for i in 0..rows {
-                        // Horizontal lines
-                        parent.spawn(NodeBundle {
-                            style: Style {
-                                position_type: PositionType::Absolute,
-                                left: Val::Px(0.0),
-                                right: Val::Px(0.0),
-                                height: Val::Px(2.0),
-                                top: Val::Percent(i as f32 * (100.0 / (rows - 1) as f32)),
-                                ..default()
-                            },
-                            background_color: Color::srgb(0.1, 0.1, 0.1).into(),
-                            ..default()
-                        });
-                        
-                        // Vertical lines
-                        parent.spawn(NodeBundle {
-                            style: Style {
-                                position_type: PositionType::Absolute,
-                                top: Val::Px(0.0),
-                                bottom: Val::Px(0.0),
-                                width: Val::Px(2.0),
-                                left: Val::Percent(i as f32 * (100.0 / (cols - 1) as f32)),
-                                ..default()
-                            },
-                            background_color: Color::srgb(0.1, 0.1, 0.1).into(),
-                            ..default()
-                        });
-                    }
+                    spawn_control_button(parent, "Pass", ControlButton::Pass);
+                    spawn_control_button(parent, "Resign", ControlButton::Resign);
+                    spawn_control_button(parent, "Done", ControlButton::Done);
+                    spawn_control_button(parent, "Handicap", ControlButton::Handicap);
+                    spawn_control_button(parent, "Rules", ControlButton::Rules);
+                    spawn_control_button(parent, "Size", ControlButton::Size);
+                    spawn_control_button(parent, "Vs Engine", ControlButton::Engine);
+                });
 
-                    // Spawn intersection points
-                    for row in 0..rows {
-                        for col in 0..cols {
-                            spawn_intersection(parent, row, col, rows, cols);
-                        }
-                    }
+            // Moving through the game tree
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        column_gap: Val::Px(20.0),
+                        margin: UiRect::top(Val::Px(10.0)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_control_button(parent, "Back", ControlButton::Back);
+                    spawn_control_button(parent, "Forward", ControlButton::Forward);
+                    spawn_control_button(parent, "Prev Var", ControlButton::PreviousVariation);
+                    spawn_control_button(parent, "Next Var", ControlButton::NextVariation);
+                    spawn_control_button(parent, "Coords", ControlButton::Coordinates);
                 });
         });
 }
 
-fn spawn_intersection(parent: &mut ChildBuilder, row: usize, col: usize, rows: usize, cols: usize) {
+fn spawn_control_button(parent: &mut ChildBuilder, label: &str, control: ControlButton) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(120.0),
+                    height: Val::Px(40.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::srgb(0.25, 0.25, 0.25).into(),
+                border_radius: BorderRadius::all(Val::Px(6.0)),
+                ..default()
+            },
+            control,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ));
+        });
+}
+
+/**
+ * Pixels between neighbouring lines on a board of width columns and height rows
+ */
+fn line_spacing(width: usize, height: usize) -> f32 {
+    BOARD_PIXELS / (width.max(height) - 1) as f32
+}
+
+/**
+ * Lays out a board of the game's size whenever it differs from the one on screen
+ * This happens on the first frame, and after a new game or an opened record changes the size.
+ * The old lines, star points, labels and intersections are removed and new ones are painted from the board.
+ */
+fn rebuild_board(
+    mut commands: Commands,
+    game_tree: Query<&Game, Changed<Game>>,
+    mut layout: ResMut<BoardLayout>,
+    mut board_area: Query<(Entity, &mut Style), With<BoardArea>>,
+) {
+    let (Ok(tree), Ok((board_entity, mut style))) = (game_tree.get_single(), board_area.get_single_mut()) else {
+        return;
+    };
+    let board = tree.state().board();
+    if layout.drawn == Some((board.width, board.height)) {
+        return;
+    }
+    layout.drawn = Some((board.width, board.height));
+
+    let spacing = line_spacing(board.width, board.height);
+    let (board_width, board_height) = (spacing * (board.width - 1) as f32, spacing * (board.height - 1) as f32);
+    style.width = Val::Px(board_width);
+    style.height = Val::Px(board_height);
+    let labels = layout.labels;
+    commands.entity(board_entity).despawn_descendants().with_children(|parent| {
+        // Horizontal lines
+        for row in 0..board.height {
+            parent.spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    height: Val::Px(2.0),
+                    top: Val::Px(row as f32 * spacing - 1.0),
+                    ..default()
+                },
+                background_color: Color::srgb(0.1, 0.1, 0.1).into(),
+                ..default()
+            });
+        }
+
+        // Vertical lines
+        for col in 0..board.width {
+            parent.spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(0.0),
+                    bottom: Val::Px(0.0),
+                    width: Val::Px(2.0),
+                    left: Val::Px(col as f32 * spacing - 1.0),
+                    ..default()
+                },
+                background_color: Color::srgb(0.1, 0.1, 0.1).into(),
+                ..default()
+            });
+        }
+
+        for (row, col) in go::game::star_points(board.width, board.height) {
+            parent.spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Px(8.0),
+                    height: Val::Px(8.0),
+                    left: Val::Px(col as f32 * spacing - 4.0),
+                    top: Val::Px(row as f32 * spacing - 4.0),
+                    ..default()
+                },
+                background_color: Color::srgb(0.1, 0.1, 0.1).into(),
+                border_radius: BorderRadius::all(Val::Px(4.0)),
+                ..default()
+            });
+        }
+
+        // Coordinates above and below each column and left and right of each row
+        for col in 0..board.width {
+            let left = col as f32 * spacing - 15.0;
+            for top in [-32.0, board_height + 10.0] {
+                spawn_label(parent, CoordinateLabel::Column(col), labels.column_label(col), left, top);
+            }
+        }
+        for row in 0..board.height {
+            let top = row as f32 * spacing - 11.0;
+            for left in [-45.0, board_width + 15.0] {
+                let text = labels.row_label(row, board.height);
+                spawn_label(parent, CoordinateLabel::Row(row), text, left, top);
+            }
+        }
+
+        // Spawn intersection points
+        for (row, col) in board.intersections() {
+            spawn_intersection(parent, row, col, spacing, board.point_state(row, col));
+        }
+    });
+}
+
+fn spawn_label(parent: &mut ChildBuilder, label: CoordinateLabel, text: String, left: f32, top: f32) {
+    parent.spawn((
+        TextBundle::from_section(
+            text,
+            TextStyle {
+                font_size: 18.0,
+                color: Color::srgb(0.85, 0.85, 0.85),
+                ..default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            width: Val::Px(30.0),
+            left: Val::Px(left),
+            top: Val::Px(top),
+            ..default()
+        }),
+        label,
+    ));
+}
+
+/**
+ * Rewrites the coordinate labels after the Coords button changes their style
+ */
+fn relabel_board(
+    layout: Res<BoardLayout>,
+    game_tree: Query<&Game>,
+    mut labels: Query<(&CoordinateLabel, &mut Text)>,
+) {
+    if !layout.is_changed() {
+        return;
+    }
+    if let Ok(tree) = game_tree.get_single() {
+        let height = tree.state().board().height;
+        for (label, mut text) in labels.iter_mut() {
+            text.sections[0].value = match *label {
+                CoordinateLabel::Column(col) => layout.labels.column_label(col),
+                CoordinateLabel::Row(row) => layout.labels.row_label(row, height),
+            };
+        }
+    }
+}
+
+fn spawn_intersection(parent: &mut ChildBuilder, row: usize, col: usize, spacing: f32, point: PointState) {
+    // Neighbouring stones may not overlap on the larger boards
+    let size = (spacing * 0.9).min(30.0);
     parent.spawn((
         ButtonBundle {
             style: Style {
-                width: Val::Px(30.0),
-                height: Val::Px(30.0),
+                width: Val::Px(size),
+                height: Val::Px(size),
                 position_type: PositionType::Absolute,
-                left: Val::Percent(col as f32 * (100.0 / (cols - 1) as f32) - 2.0),
-                top: Val::Percent(row as f32 * (100.0 / (rows - 1) as f32) - 2.0),
+                left: Val::Px(col as f32 * spacing - size / 2.0),
+                top: Val::Px(row as f32 * spacing - size / 2.0),
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
                 ..default()
@@ -164,17 +467,17 @@ fn spawn_intersection(parent: &mut ChildBuilder, row: usize, col: usize, rows: u
         GridSquare { row, col },
     ))
     .with_children(|parent| {
-        // Stone visual (initially invisible)
+        // Stone visual, invisible on an empty point
         parent.spawn((
             NodeBundle {
                 style: Style {
-                    width: Val::Px(28.0),
-                    height: Val::Px(28.0),
+                    width: Val::Px(size - 2.0),
+                    height: Val::Px(size - 2.0),
                     border: UiRect::all(Val::Px(0.0)),
                     ..default()
                 },
-                background_color: Color::srgba(0.0, 0.0, 0.0, 0.0).into(),
-                border_radius: BorderRadius::all(Val::Px(14.0)), // Make it circular
+                background_color: stone_color(point).into(),
+                border_radius: BorderRadius::all(Val::Px(size / 2.0 - 1.0)), // Make it circular
                 ..default()
             },
             StoneBackground,
@@ -186,22 +489,71 @@ fn spawn_intersection(parent: &mut ChildBuilder, row: usize, col: usize, rows: u
 #[derive(Component)]
 struct StoneBackground;
 
+//The wooden board, parent of the lines, star points, labels and intersections
+#[derive(Component)]
+struct BoardArea;
+
+//Flash on an intersection whose move was refused, cleared when the timer runs out
+#[derive(Component)]
+struct RejectionFlash(Timer);
+
+//Tooltip next to a refused intersection saying why, removed when the timer runs out
+#[derive(Component)]
+struct RejectionTooltip(Timer);
+
+//A coordinate along the edge of the board, for the column or row it labels
+#[derive(Component, Clone, Copy)]
+enum CoordinateLabel {
+    Column(usize),
+    Row(usize),
+}
+
 // Add this near other components
 #[derive(Component)]
 struct TurnText;
 
+#[derive(Component)]
+struct StatusText;
+
+#[derive(Component)]
+struct VariationText;
+
+#[derive(Component)]
+struct GameOverText;
+
+/**
+ * Buttons that act on the game rather than on an intersection
+ */
+#[derive(Component, Clone, Copy)]
+enum ControlButton {
+    Pass,
+    Resign,
+    Done,
+    Handicap,
+    Rules,
+    Size,
+    Engine,
+    Back,
+    Forward,
+    PreviousVariation,
+    NextVariation,
+    Coordinates,
+}
+
 /**
  * Handles all interaction with the game board squares.
  * This includes:
  * - Processing clicks to place stones
  * - Updating square colors based on the current player's turn
- * - Showing hover effects when moving over squares
+ * - Showing a ghost stone of the player to move over points they may play
+ * - Flashing a refused point with a tooltip saying why
  * - Enforcing game rules through the place_stone function
  * 
  * The color of placed stones will be:
  * - White for the white player
  * - Black for the black player
  */
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 fn grid_button_interaction(
     interaction_query: Query<
         (&Interaction, &GridSquare, Entity),
@@ -209,75 +561,577 @@ fn grid_button_interaction(
     >,
     grid_squares: Query<(&GridSquare, Entity), With<Button>>,
     mut stone_query: Query<(&mut BackgroundColor, &Parent), With<StoneBackground>>,
-    mut board: Query<&mut Board>,
-    mut player_query: Query<&mut PlayerModel, With<Player>>,
-    mut turn_text: Query<&mut Text, With<TurnText>>,
+    mut game_tree: Query<&mut Game>,
+    mut status_text: Query<&mut Text, With<StatusText>>,
+    opponent: Res<EngineOpponent>,
+    board_area: Query<Entity, With<BoardArea>>,
+    mut commands: Commands,
 ) {
     for (interaction, grid_square, button_entity) in interaction_query.iter() {
+        // A ghost stone of the player to move on a point they may play, the board as it is anywhere else
+        if *interaction != Interaction::Pressed {
+            if let (Ok(tree), Some((mut preview, _))) = (
+                game_tree.get_single(),
+                stone_query.iter_mut().find(|(_, parent)| parent.get() == button_entity),
+            ) {
+                let state = tree.state();
+                let (row, col) = (grid_square.row, grid_square.col);
+                // Scoring paints territory over the stones, so the preview stays out of its way
+                if state.board().phase == GamePhase::Playing {
+                    *preview = match *interaction {
+                        Interaction::Hovered if !opponent.is_thinking(tree) && state.check_play(row, col).is_ok() => {
+                            stone_color(state.to_move().into()).with_alpha(0.5).into()
+                        }
+                        _ => stone_color(state.board().point_state(row, col)).into(),
+                    };
+                }
+            }
+            continue;
+        }
         if let Some(_stone_color) = stone_query.iter_mut()
             .find(|(_, parent)| parent.get() == button_entity) {
             if let Interaction::Pressed = *interaction {
-                if let Ok(mut board) = board.get_single_mut() {
-                    let mut current_player = None;
-                    let mut opponent_player = None;
-
-                    for player_model in player_query.iter_mut() {
-                        if (board.is_white_turn && player_model.get_player_color() == game::WHITE) ||
-                           (!board.is_white_turn && player_model.get_player_color() == game::BLACK) {
-                            current_player = Some(player_model);
-                        } else {
-                            opponent_player = Some(player_model);
-                        }
+                if let Ok(mut tree) = game_tree.get_single_mut() {
+                    // After two passes a click marks the chain dead, in seki, or alive again
+                    if tree.state().board().phase == GamePhase::Scoring {
+                        tree.toggle_chain_status(grid_square.row, grid_square.col);
+                        continue;
+                    }
+                    if opponent.is_thinking(&tree) {
+                        continue;
+                    }
+
+                    let placed = tree.play(Move::Play(grid_square.row, grid_square.col));
+
+                    // Tell the player why a move was refused
+                    if let Ok(mut text) = status_text.get_single_mut() {
+                        text.sections[0].value = match &placed {
+                            Ok(_) => String::new(),
+                            Err(error) => error.to_string(),
+                        };
                     }
 
-                    if let (Some(mut current), Some(mut opponent)) = (current_player, opponent_player) {
-                        let placed = game::place_stone(
-                            &mut board,
-                            &mut current,
-                            &mut opponent,
-                            grid_square.row,
-                            grid_square.col,
-                        );
-
-                        if placed {
-                            // Update all stones
-                            for row in 0..board.board_size {
-                                for col in 0..board.board_size {
-                                    let color = board.board_state[row][col].get_player_color();
-                                    if let Some(mut square_color) = stone_query.iter_mut()
-                                        .find(|(_, parent)| {
-                                            if let Ok((grid_square, _)) = grid_squares.get(parent.get()) {
-                                                grid_square.row == row && grid_square.col == col
-                                            } else {
-                                                false
-                                            }
-                                        }) {
-                                        match color {
-                                            game::WHITE => *square_color.0 = Color::srgba(1.0, 1.0, 1.0, 1.0).into(),
-                                            game::BLACK => *square_color.0 = Color::srgba(0.0, 0.0, 0.0, 1.0).into(),
-                                            game::WHITE_TERR => *square_color.0 = Color::srgba(0.9, 0.9, 0.9, 0.8).into(),
-                                            game::BLACK_TERR => *square_color.0 = Color::srgba(0.2, 0.2, 0.2, 0.8).into(),
-                                            game::EMPTY => *square_color.0 = Color::srgba(0.0, 0.0, 0.0, 0.0).into(),
-                                            _ => {}
-                                        }
+                    // Flash the point and say why next to it
+                    if let (Err(error), Ok(board_entity)) = (&placed, board_area.get_single()) {
+                        let board = tree.state().board();
+                        let spacing = line_spacing(board.width, board.height);
+                        commands.entity(button_entity).insert((
+                            RejectionFlash(Timer::from_seconds(REJECTION_SECONDS, TimerMode::Once)),
+                            BackgroundColor(Color::srgba(0.9, 0.1, 0.1, 0.6)),
+                        ));
+                        commands.entity(board_entity).with_children(|parent| {
+                            spawn_tooltip(parent, rejection_reason(error), grid_square.row, grid_square.col, spacing);
+                        });
+                    }
+
+                    if let Ok(outcome) = placed {
+                        // Only the new stone, the captured stones and any suicided stones have changed
+                        let mut changed = outcome.captured;
+                        changed.extend(outcome.suicide);
+                        changed.push((grid_square.row, grid_square.col));
+                        for (row, col) in changed {
+                            let state = tree.state().board().point_state(row, col);
+                            if let Some(mut square_color) = stone_query.iter_mut()
+                                .find(|(_, parent)| {
+                                    if let Ok((grid_square, _)) = grid_squares.get(parent.get()) {
+                                        grid_square.row == row && grid_square.col == col
+                                    } else {
+                                        false
                                     }
-                                }
+                                }) {
+                                *square_color.0 = stone_color(state).into();
                             }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Short reason shown next to a refused point
+ */
+fn rejection_reason(error: &MoveError) -> &'static str {
+    match error {
+        MoveError::Occupied => "Occupied",
+        MoveError::Suicide => "Suicide",
+        MoveError::Ko(_) => "Ko",
+        _ => "Illegal move",
+    }
+}
 
-                            // Update turn text
-                            if let Ok(mut text) = turn_text.get_single_mut() {
-                                if board.is_white_turn {
-                                    text.sections[0].value = "White's Turn".to_string();
-                                    text.sections[0].style.color = Color::WHITE;
-                                } else {
-                                    text.sections[0].value = "Black's Turn".to_string();
-                                    text.sections[0].style.color = Color::BLACK;
+fn spawn_tooltip(parent: &mut ChildBuilder, text: &str, row: usize, col: usize, spacing: f32) {
+    parent.spawn((
+        TextBundle::from_section(
+            text,
+            TextStyle {
+                font_size: 16.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            width: Val::Px(100.0),
+            left: Val::Px(col as f32 * spacing - 50.0),
+            // Above the point, or below it on the top row so it stays on the board
+            top: Val::Px(if row == 0 { spacing / 2.0 + 4.0 } else { row as f32 * spacing - spacing / 2.0 - 24.0 }),
+            padding: UiRect::all(Val::Px(2.0)),
+            ..default()
+        })
+        .with_background_color(Color::srgba(0.1, 0.1, 0.1, 0.85)),
+        ZIndex::Global(1),
+        RejectionTooltip(Timer::from_seconds(REJECTION_SECONDS, TimerMode::Once)),
+    ));
+}
+
+/**
+ * Fades out the flash and tooltip of a refused move once their time is up
+ */
+fn clear_rejections(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut flashes: Query<(Entity, &mut RejectionFlash, &mut BackgroundColor)>,
+    mut tooltips: Query<(Entity, &mut RejectionTooltip)>,
+) {
+    for (entity, mut flash, mut background) in flashes.iter_mut() {
+        if flash.0.tick(time.delta()).finished() {
+            *background = Color::NONE.into();
+            commands.entity(entity).remove::<RejectionFlash>();
+        }
+    }
+    for (entity, mut tooltip) in tooltips.iter_mut() {
+        if tooltip.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/**
+ * Handles the Pass, Resign, Done and Handicap buttons.
+ * Resigning is done by the player whose turn it is.
+ * Done accepts the dead stones marked during scoring and settles the result.
+ * Handicap cycles through 0 and 2-9 fixed handicap stones, only before the first move and on 9x9, 13x13 and 19x19.
+ * Rules cycles through the rule set presets and starts a new game under the chosen rules, only before the first move.
+ * Size starts a new game on the next of 9x9, 13x13 and 19x19, only before the first move.
+ * Back and Forward step through the game tree, a move played after stepping back starts a variation.
+ * Prev Var and Next Var switch between the variations at the current move.
+ * Vs Engine cycles the engine through playing white, playing black and off.
+ */
+#[allow(clippy::type_complexity)]
+fn control_button_interaction(
+    interaction_query: Query<(&Interaction, &ControlButton), Changed<Interaction>>,
+    grid_squares: Query<&GridSquare, With<Button>>,
+    mut stone_query: Query<(&mut BackgroundColor, &Parent), With<StoneBackground>>,
+    mut game_tree: Query<&mut Game>,
+    mut status_text: Query<&mut Text, With<StatusText>>,
+    mut opponent: ResMut<EngineOpponent>,
+    mut layout: ResMut<BoardLayout>,
+) {
+    for (interaction, control) in interaction_query.iter() {
+        if let Interaction::Pressed = *interaction {
+            if let Ok(mut tree) = game_tree.get_single_mut() {
+                // Passing and resigning act for the player to move, which is not the players while the engine thinks
+                if matches!(control, ControlButton::Pass | ControlButton::Resign) && opponent.is_thinking(&tree) {
+                    continue;
+                }
+                match control {
+                    ControlButton::Pass => {
+                        let _ = tree.play(Move::Pass);
+                    }
+                    ControlButton::Done => {
+                        tree.finish_scoring();
+                    }
+                    ControlButton::Resign => {
+                        // The player whose turn it is resigns
+                        let _ = tree.play(Move::Resign);
+                    }
+                    ControlButton::Handicap => {
+                        if tree.state().board().has_started() {
+                            continue;
+                        }
+                        // Fixed handicap points are only defined for the standard square boards
+                        let board = tree.state().board();
+                        let available = board.is_square() && game::handicap_points(board.width, 2).is_some();
+                        if !available {
+                            if let Ok(mut text) = status_text.get_single_mut() {
+                                text.sections[0].value = format!(
+                                    "No handicap on {}x{}, only on 9x9, 13x13 and 19x19", board.width, board.height
+                                );
+                            }
+                            continue;
+                        }
+                        let stones = match tree.state().board().handicap {
+                            0 => 2,
+                            9 => 0,
+                            current => current + 1,
+                        };
+
+                        // Start over from an empty board under the same rules
+                        let board = tree.state().board();
+                        let mut state = GameState::with_dimensions(board.width, board.height, board.rules);
+                        if stones > 0 {
+                            state.place_fixed_handicap(stones);
+                        }
+                        **tree = GameTree::new(state);
+                        opponent.requested = None;
+
+                        repaint_stones(tree.state(), &grid_squares, &mut stone_query);
+                        if let Ok(mut text) = status_text.get_single_mut() {
+                            text.sections[0].value = format!("Handicap: {} stones", tree.state().board().handicap);
+                        }
+                    }
+                    ControlButton::Rules => {
+                        // The rules are chosen when a game starts, not changed under one in progress
+                        if tree.state().board().has_started() {
+                            continue;
+                        }
+                        let presets = RuleSet::presets();
+                        let current = presets.iter()
+                            .position(|rules| rules.name == tree.state().board().rules.name)
+                            .unwrap_or(0);
+                        let rules = presets[(current + 1) % presets.len()];
+                        let board = tree.state().board();
+                        **tree = GameTree::new(GameState::with_dimensions(board.width, board.height, rules));
+                        opponent.requested = None;
+
+                        for (mut square_color, _) in stone_query.iter_mut() {
+                            *square_color = stone_color(PointState::Empty).into();
+                        }
+                        if let Ok(mut text) = status_text.get_single_mut() {
+                            text.sections[0].value = format!("Rules: {}, komi {}", rules.name, rules.komi);
+                        }
+                    }
+                    ControlButton::Size => {
+                        if tree.state().board().has_started() {
+                            continue;
+                        }
+                        // Start over on the next standard size under the same rules, rebuild_board lays it out
+                        let board = tree.state().board();
+                        let next = BOARD_SIZES.iter()
+                            .position(|&size| board.is_square() && size == board.width)
+                            .map_or(BOARD_SIZES[0], |current| BOARD_SIZES[(current + 1) % BOARD_SIZES.len()]);
+                        **tree = GameTree::new(GameState::with_rules(next, board.rules));
+                        opponent.requested = None;
+                        if let Ok(mut text) = status_text.get_single_mut() {
+                            text.sections[0].value = format!("Board: {}x{}", next, next);
+                        }
+                    }
+                    ControlButton::Engine => {
+                        let message = match opponent.command.clone() {
+                            None => "Start the game with --engine \"<command>\" to play an engine".to_string(),
+                            Some(command) => {
+                                opponent.color = match opponent.color {
+                                    None => Some(game::Color::White),
+                                    Some(game::Color::White) => Some(game::Color::Black),
+                                    Some(game::Color::Black) => None,
+                                };
+                                opponent.requested = None;
+                                match opponent.color {
+                                    Some(color) => {
+                                        opponent.worker.get_or_insert_with(|| EngineWorker::spawn(command, ENGINE_TIMEOUT));
+                                        format!("Engine plays {}", color)
+                                    }
+                                    None => "Engine off".to_string(),
                                 }
                             }
+                        };
+                        if let Ok(mut text) = status_text.get_single_mut() {
+                            text.sections[0].value = message;
                         }
                     }
+                    ControlButton::Back | ControlButton::Forward
+                    | ControlButton::PreviousVariation | ControlButton::NextVariation => {
+                        let changed = match control {
+                            ControlButton::Back => tree.back(),
+                            ControlButton::Forward => tree.forward(),
+                            ControlButton::PreviousVariation => tree.previous_variation(),
+                            _ => tree.next_variation(),
+                        };
+                        show_navigation(changed, tree.state(), &grid_squares, &mut stone_query, &mut status_text);
+                    }
+                    ControlButton::Coordinates => {
+                        layout.labels = layout.labels.next();
+                        if let Ok(mut text) = status_text.get_single_mut() {
+                            text.sections[0].value = format!("Coordinates: {}", layout.labels);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/**
+ * When it is the engine's turn, send it the game and play the move it answers with.
+ * The engine runs on its own thread, so this only checks for an answer each frame.
+ * An engine error or an illegal move from the engine turns the engine off.
+ */
+#[allow(clippy::type_complexity)]
+fn engine_turn(
+    mut opponent: ResMut<EngineOpponent>,
+    grid_squares: Query<&GridSquare, With<Button>>,
+    mut stone_query: Query<(&mut BackgroundColor, &Parent), With<StoneBackground>>,
+    mut game_tree: Query<&mut Game>,
+    mut status_text: Query<&mut Text, With<StatusText>>,
+) {
+    let opponent = &mut *opponent;
+    if let (Some(worker), Ok(mut tree)) = (&opponent.worker, game_tree.get_single_mut()) {
+        let mut message = None;
+        if let Some(reply) = worker.try_reply() {
+            // Drop the answer if the players have moved on or turned the engine off meanwhile
+            let requested = opponent.requested.take();
+            if opponent.is_thinking(&tree) && requested == Some(EngineGame::from_tree(&tree)) {
+                match reply.map_err(|error| error.to_string())
+                    .and_then(|next_move| tree.play(next_move).map_err(|error| format!("The engine played an illegal move: {error}"))) {
+                    Ok(_) => {
+                        repaint_stones(tree.state(), &grid_squares, &mut stone_query);
+                        message = Some(String::new());
+                    }
+                    Err(error) => {
+                        opponent.color = None;
+                        message = Some(format!("{error}. Engine off"));
+                    }
                 }
             }
         }
+        if opponent.requested.is_none() && opponent.is_thinking(&tree) {
+            let game = EngineGame::from_tree(&tree);
+            worker.request(game.clone());
+            opponent.requested = Some(game);
+            message = Some("Engine is thinking...".to_string());
+        }
+        if let (Some(message), Ok(mut text)) = (message, status_text.get_single_mut()) {
+            text.sections[0].value = message;
+        }
+    }
+}
+
+/**
+ * Keyboard shortcuts for the game tree:
+ * - Ctrl+Z or Left steps back, Ctrl+Y, Ctrl+Shift+Z or Right steps forward
+ * - Up and Down switch to the previous and next variation
+ */
+#[allow(clippy::type_complexity)]
+fn navigation_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    grid_squares: Query<&GridSquare, With<Button>>,
+    mut stone_query: Query<(&mut BackgroundColor, &Parent), With<StoneBackground>>,
+    mut game_tree: Query<&mut Game>,
+    mut status_text: Query<&mut Text, With<StatusText>>,
+) {
+    let control = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let back = keys.just_pressed(KeyCode::ArrowLeft) || (control && !shift && keys.just_pressed(KeyCode::KeyZ));
+    let forward = keys.just_pressed(KeyCode::ArrowRight)
+        || (control && (keys.just_pressed(KeyCode::KeyY) || (shift && keys.just_pressed(KeyCode::KeyZ))));
+    if !(back || forward || keys.any_just_pressed([KeyCode::ArrowUp, KeyCode::ArrowDown])) {
+        return;
     }
-}
\ No newline at end of file
+    if let Ok(mut tree) = game_tree.get_single_mut() {
+        let changed = if back {
+            tree.back()
+        } else if forward {
+            tree.forward()
+        } else if keys.just_pressed(KeyCode::ArrowUp) {
+            tree.previous_variation()
+        } else {
+            tree.next_variation()
+        };
+        show_navigation(changed, tree.state(), &grid_squares, &mut stone_query, &mut status_text);
+    }
+}
+
+/**
+ * Repaint the board after a step through the game tree, or say why the step could not be made
+ */
+fn show_navigation(
+    changed: Result<bool, MoveError>,
+    state: &GameState,
+    grid_squares: &Query<&GridSquare, With<Button>>,
+    stone_query: &mut Query<(&mut BackgroundColor, &Parent), With<StoneBackground>>,
+    status_text: &mut Query<&mut Text, With<StatusText>>,
+) {
+    let message = match changed {
+        Ok(true) => {
+            repaint_stones(state, grid_squares, stone_query);
+            String::new()
+        }
+        Ok(false) => return,
+        Err(error) => format!("This move cannot be replayed: {error}"),
+    };
+    if let Ok(mut text) = status_text.get_single_mut() {
+        text.sections[0].value = message;
+    }
+}
+
+/**
+ * Ctrl+S saves the game tree, variations included, as an SGF file in the working directory
+ * Ctrl+O opens the first game in that file at its starting position
+ */
+#[allow(clippy::type_complexity)]
+fn save_and_load_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    grid_squares: Query<&GridSquare, With<Button>>,
+    mut stone_query: Query<(&mut BackgroundColor, &Parent), With<StoneBackground>>,
+    mut game_tree: Query<&mut Game>,
+    mut status_text: Query<&mut Text, With<StatusText>>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    if let Ok(mut tree) = game_tree.get_single_mut() {
+        let message = if keys.just_pressed(KeyCode::KeyS) {
+            match std::fs::write(SAVE_PATH, sgf::export(&tree)) {
+                Ok(()) => format!("Saved {}", SAVE_PATH),
+                Err(error) => format!("Could not save {}: {}", SAVE_PATH, error),
+            }
+        } else if keys.just_pressed(KeyCode::KeyO) {
+            let loaded = std::fs::read_to_string(SAVE_PATH)
+                .map_err(|error| error.to_string())
+                .and_then(|text| sgf::import(&text).map_err(|error| error.to_string()));
+            match loaded {
+                // A record of another size is laid out again by rebuild_board
+                Ok(mut games) => {
+                    **tree = games.swap_remove(0);
+                    repaint_stones(tree.state(), &grid_squares, &mut stone_query);
+                    format!("Opened {}", SAVE_PATH)
+                }
+                Err(error) => format!("Could not open {}: {}", SAVE_PATH, error),
+            }
+        } else {
+            return;
+        };
+        eprintln!("{}", message);
+        if let Ok(mut text) = status_text.get_single_mut() {
+            text.sections[0].value = message;
+        }
+    }
+}
+
+/**
+ * Paint every intersection from the board, used when more than a move's worth of points may have changed
+ * This also clears any territory markers left from scoring.
+ */
+#[allow(clippy::type_complexity)]
+fn repaint_stones(
+    state: &GameState,
+    grid_squares: &Query<&GridSquare, With<Button>>,
+    stone_query: &mut Query<(&mut BackgroundColor, &Parent), With<StoneBackground>>,
+) {
+    for (mut square_color, parent) in stone_query.iter_mut() {
+        if let Ok(grid_square) = grid_squares.get(parent.get()) {
+            let point = state.board().point_state(grid_square.row, grid_square.col);
+            *square_color = stone_color(point).into();
+        }
+    }
+}
+
+/**
+ * Keeps the turn indicator, the game-over banner and the move and variation line in sync with the board.
+ * Only runs its updates when the board has changed.
+ */
+#[allow(clippy::type_complexity)]
+fn update_game_status(
+    game_tree: Query<&Game, Changed<Game>>,
+    mut turn_text: Query<&mut Text, (With<TurnText>, Without<GameOverText>, Without<VariationText>)>,
+    mut game_over_text: Query<&mut Text, (With<GameOverText>, Without<TurnText>, Without<VariationText>)>,
+    mut variation_text: Query<&mut Text, (With<VariationText>, Without<TurnText>, Without<GameOverText>)>,
+) {
+    if let Ok(tree) = game_tree.get_single() {
+        let board = tree.state().board();
+        if let Ok(mut text) = turn_text.get_single_mut() {
+            if board.phase != GamePhase::Playing {
+                text.sections[0].value = String::new();
+            } else if board.is_white_turn {
+                text.sections[0].value = "White's Turn".to_string();
+                text.sections[0].style.color = Color::WHITE;
+            } else {
+                text.sections[0].value = "Black's Turn".to_string();
+                text.sections[0].style.color = Color::BLACK;
+            }
+        }
+
+        if let Ok(mut text) = game_over_text.get_single_mut() {
+            text.sections[0].value = match (board.phase, board.result) {
+                (GamePhase::Playing, _) => String::new(),
+                (GamePhase::Scoring, _) => "Click chains to mark them dead or in seki, then press Done".to_string(),
+                (_, Some(result)) => format!("Game over: {result}"),
+                (_, None) => "Game over".to_string(),
+            };
+        }
+
+        if let Ok(mut text) = variation_text.get_single_mut() {
+            let current = tree.current();
+            let (index, count) = tree.variation(current);
+            let mut line = if tree.is_main_line(current) {
+                format!("Move {}, main line", tree.move_number())
+            } else {
+                format!("Move {}, variation {} of {}", tree.move_number(), index + 1, count)
+            };
+            let next = tree.node(current).children.len();
+            if next > 1 {
+                line.push_str(&format!(", {} variations follow", next));
+            }
+            let comment = &tree.node(current).comment;
+            if !comment.is_empty() {
+                line.push_str(&format!("\n{}", comment));
+            }
+            text.sections[0].value = line;
+        }
+    }
+}
+
+/**
+ * Color used to draw the stone on an intersection, transparent for an empty point
+ */
+fn stone_color(point: PointState) -> Color {
+    match point {
+        PointState::Stone(game::Color::White) => Color::srgba(1.0, 1.0, 1.0, 1.0),
+        PointState::Stone(game::Color::Black) => Color::srgba(0.0, 0.0, 0.0, 1.0),
+        PointState::Empty => Color::srgba(0.0, 0.0, 0.0, 0.0),
+    }
+}
+
+/**
+ * Color of the territory marker shown after scoring, None for a neutral point
+ */
+fn territory_color(owner: TerritoryOwner) -> Option<Color> {
+    match owner {
+        TerritoryOwner::White => Some(Color::srgba(0.9, 0.9, 0.9, 0.8)),
+        TerritoryOwner::Black => Some(Color::srgba(0.2, 0.2, 0.2, 0.8)),
+        TerritoryOwner::Neutral => None,
+    }
+}
+
+/**
+ * While scoring and once the game has been scored, paint every intersection with its owner
+ * so the players can see which empty points and dead stones count as territory.
+ */
+fn draw_territory(
+    game_tree: Query<&Game, Changed<Game>>,
+    grid_squares: Query<&GridSquare, With<Button>>,
+    mut stone_query: Query<(&mut BackgroundColor, &Parent), With<StoneBackground>>,
+) {
+    if let Ok(tree) = game_tree.get_single() {
+        let board = tree.state().board();
+        if board.phase == GamePhase::Playing || matches!(board.result, Some(game::GameResult::Resignation { .. })) {
+            return;
+        }
+        let territory = scoring::territory_map(board);
+        for (mut square_color, parent) in stone_query.iter_mut() {
+            if let Ok(grid_square) = grid_squares.get(parent.get()) {
+                let (row, col) = (grid_square.row, grid_square.col);
+                // Living stones are drawn as usual, dead stones outside any territory are hidden
+                let stone = match board.get_chain_status(row, col) {
+                    ChainStatus::Dead => PointState::Empty,
+                    _ => board.point_state(row, col),
+                };
+                let color = territory_color(territory[row][col]).unwrap_or_else(|| stone_color(stone));
+                *square_color = color.into();
+            }
+        }
+    }
+}
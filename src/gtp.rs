@@ -0,0 +1,372 @@
+use std::io::{self, BufRead, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::game::{Color, GameResult, GameState, Move, PointState, RuleSet, MAX_BOARD_SIZE, MIN_BOARD_SIZE};
+use crate::scoring;
+
+//Go Text Protocol version 2, so the rules engine can be driven by Sabaki, gogui, twogtp and similar tools.
+//Logging from the engine goes to stderr, stdout only carries responses.
+
+const COMMANDS: &[&str] = &[
+    "protocol_version",
+    "name",
+    "version",
+    "known_command",
+    "list_commands",
+    "quit",
+    "boardsize",
+    "rectangular_boardsize",
+    "clear_board",
+    "komi",
+    "play",
+    "genmove",
+    "undo",
+    "showboard",
+    "final_score",
+];
+
+//Column letters skip I so it cannot be mistaken for J
+pub(crate) const COLUMNS: &[u8] = b"ABCDEFGHJKLMNOPQRSTUVWXYZ";
+
+/**
+ * Answer GTP commands from stdin on stdout until quit or the end of input
+ */
+pub fn run_stdio() -> io::Result<()> {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |elapsed| elapsed.as_nanos() as u64);
+    run(io::stdin().lock(), io::stdout().lock(), seed)
+}
+
+/**
+ * Answer GTP commands read from input, seed picks the moves genmove plays
+ */
+pub fn run(input: impl BufRead, mut output: impl Write, seed: u64) -> io::Result<()> {
+    let mut engine = Engine::new(seed);
+    for line in input.lines() {
+        let line = line?;
+        let command = clean_line(&line);
+        let mut words = command.split_whitespace();
+        let first = match words.next() {
+            Some(first) => first,
+            None => continue,
+        };
+        // An optional numeric id is echoed back in the response
+        let (id, name) = if first.bytes().all(|byte| byte.is_ascii_digit()) {
+            (first, words.next().unwrap_or(""))
+        } else {
+            ("", first)
+        };
+        let args: Vec<&str> = words.collect();
+        match engine.execute(name, &args) {
+            Ok(text) if text.is_empty() => write!(output, "={}\n\n", id)?,
+            Ok(text) => write!(output, "={} {}\n\n", id, text)?,
+            Err(message) => write!(output, "?{} {}\n\n", id, message)?,
+        }
+        output.flush()?;
+        if name == "quit" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+//Comments after # are dropped, tabs become spaces and other control characters are removed
+fn clean_line(line: &str) -> String {
+    let line = line.split('#').next().unwrap_or("");
+    line.chars()
+        .filter_map(|character| match character {
+            '\t' => Some(' '),
+            _ if character.is_control() => None,
+            _ => Some(character),
+        })
+        .collect()
+}
+
+struct Engine {
+    state: GameState,
+    rules: RuleSet,
+    seed: u64,
+}
+
+impl Engine {
+    fn new(seed: u64) -> Self {
+        let rules = RuleSet::default();
+        Engine {
+            state: GameState::with_rules(19, rules),
+            rules,
+            seed: seed.max(1),
+        }
+    }
+
+    fn execute(&mut self, name: &str, args: &[&str]) -> Result<String, String> {
+        let (width, height) = (self.state.board().width, self.state.board().height);
+        match name {
+            "protocol_version" => Ok("2".to_string()),
+            "name" => Ok("rust-go".to_string()),
+            "version" => Ok(env!("CARGO_PKG_VERSION").to_string()),
+            "known_command" => Ok(COMMANDS.contains(args.first().unwrap_or(&"")).to_string()),
+            "list_commands" => Ok(COMMANDS.join("\n")),
+            "quit" => Ok(String::new()),
+            "boardsize" => {
+                let size = parse_size(argument(args, 0)?)?;
+                self.state = GameState::with_rules(size, self.rules);
+                Ok(String::new())
+            }
+            // Not part of GTP 2, but understood by engines such as KataGo
+            "rectangular_boardsize" => {
+                let width = parse_size(argument(args, 0)?)?;
+                let height = parse_size(argument(args, 1)?)?;
+                self.state = GameState::with_dimensions(width, height, self.rules);
+                Ok(String::new())
+            }
+            "clear_board" => {
+                self.state = GameState::with_dimensions(width, height, self.rules);
+                Ok(String::new())
+            }
+            "komi" => {
+                let komi: f32 = argument(args, 0)?.parse().map_err(|_| "syntax error")?;
+                self.rules.komi = komi;
+                self.state.set_komi(komi);
+                Ok(String::new())
+            }
+            "play" => {
+                let player_color = parse_color(argument(args, 0)?)?;
+                let next_move = parse_vertex(argument(args, 1)?, width, height)?;
+                // GTP lets either color move at any time, after both players passed too
+                let previous = self.state.to_move();
+                self.state.set_to_move(player_color);
+                if let Err(error) = self.state.play_on(next_move) {
+                    self.state.set_to_move(previous);
+                    return Err(format!("illegal move: {}", error));
+                }
+                Ok(String::new())
+            }
+            "genmove" => {
+                let player_color = parse_color(argument(args, 0)?)?;
+                let previous = self.state.to_move();
+                self.state.set_to_move(player_color);
+                // A move the engine cannot play is reported instead of being answered as played
+                let next_move = self.choose_move();
+                if let Err(error) = self.state.play_on(next_move) {
+                    self.state.set_to_move(previous);
+                    return Err(format!("cannot play: {}", error));
+                }
+                Ok(vertex_text(next_move, height))
+            }
+            "undo" => {
+                if self.state.undo() {
+                    Ok(String::new())
+                } else {
+                    Err("cannot undo".to_string())
+                }
+            }
+            "showboard" => Ok(self.show_board()),
+            "final_score" => Ok(match scoring::score_board(self.state.board()).result() {
+                GameResult::Draw => "0".to_string(),
+                result => result.to_string(),
+            }),
+            _ => Err("unknown command".to_string()),
+        }
+    }
+
+    //A random legal move that does not fill one of the player's own eyes, or a pass
+    fn choose_move(&mut self) -> Move {
+        let board = self.state.board();
+        let player_color = self.state.to_move();
+        let candidates: Vec<(usize, usize)> = self.state.legal_moves()
+            .into_iter()
+            .filter(|&(row, col)| board.adjacent_points(row, col).any(|(r, c)| board.point_state(r, c) != player_color.into()))
+            .collect();
+        if candidates.is_empty() {
+            return Move::Pass;
+        }
+        // xorshift64
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        let (row, col) = candidates[(self.seed % candidates.len() as u64) as usize];
+        Move::Play(row, col)
+    }
+
+    //Row 0 is the top of the board, which GTP numbers as the board height
+    fn show_board(&self) -> String {
+        let board = self.state.board();
+        let letters: String = COLUMNS[..board.width].iter().map(|&letter| format!(" {}", letter as char)).collect();
+        let mut text = format!("\n   {}\n", letters);
+        for row in 0..board.height {
+            text.push_str(&format!("{:>2} ", board.height - row));
+            for col in 0..board.width {
+                text.push_str(match board.point_state(row, col) {
+                    PointState::Stone(Color::Black) => " X",
+                    PointState::Stone(Color::White) => " O",
+                    PointState::Empty => " .",
+                });
+            }
+            text.push_str(&format!(" {}\n", board.height - row));
+        }
+        text.push_str(&format!("   {}\n", letters));
+        text.push_str(&format!(
+            "Black has captured {} stones, white has captured {} stones",
+            board.black_captured, board.white_captured
+        ));
+        text
+    }
+}
+
+fn argument<'a>(args: &[&'a str], index: usize) -> Result<&'a str, String> {
+    args.get(index).copied().ok_or_else(|| "syntax error".to_string())
+}
+
+fn parse_size(text: &str) -> Result<usize, String> {
+    let size: usize = text.parse().map_err(|_| "syntax error")?;
+    if !(MIN_BOARD_SIZE..=MAX_BOARD_SIZE).contains(&size) {
+        return Err("unacceptable size".to_string());
+    }
+    Ok(size)
+}
+
+fn parse_color(text: &str) -> Result<Color, String> {
+    match text.to_ascii_lowercase().as_str() {
+        "b" | "black" => Ok(Color::Black),
+        "w" | "white" => Ok(Color::White),
+        _ => Err("syntax error".to_string()),
+    }
+}
+
+/**
+ * A vertex is a column letter and a row number counted from the bottom, like D4, or pass
+ * width and height are the number of columns and rows of the board
+ */
+pub fn parse_vertex(text: &str, width: usize, height: usize) -> Result<Move, String> {
+    let text = text.to_ascii_uppercase();
+    if text == "PASS" {
+        return Ok(Move::Pass);
+    }
+    let mut chars = text.chars();
+    let letter = chars.next().ok_or("syntax error")?;
+    let col = COLUMNS.iter().position(|&column| column as char == letter).ok_or("syntax error")?;
+    let number: usize = chars.as_str().parse().map_err(|_| "syntax error")?;
+    if col >= width || number == 0 || number > height {
+        return Err("illegal move: off the board".to_string());
+    }
+    Ok(Move::Play(height - number, col))
+}
+
+/**
+ * The GTP vertex for a move on a board with height rows, the reverse of parse_vertex
+ */
+pub fn vertex_text(next_move: Move, height: usize) -> String {
+    match next_move {
+        Move::Play(row, col) => format!("{}{}", COLUMNS[col] as char, height - row),
+        Move::Pass => "pass".to_string(),
+        Move::Resign => "resign".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Move;
+    use crate::gtp::{self, parse_vertex, vertex_text};
+
+    fn session(commands: &str) -> Vec<String> {
+        let mut output = Vec::new();
+        gtp::run(commands.as_bytes(), &mut output, 42).unwrap();
+        String::from_utf8(output).unwrap()
+            .split("\n\n")
+            .filter(|response| !response.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_vertices_skip_i_and_count_from_bottom() {
+        assert_eq!(parse_vertex("A1", 19, 19), Ok(Move::Play(18, 0)));
+        assert_eq!(parse_vertex("j19", 19, 19), Ok(Move::Play(0, 8)));
+        assert_eq!(parse_vertex("PASS", 9, 9), Ok(Move::Pass));
+        assert!(parse_vertex("I5", 19, 19).is_err());
+        assert!(parse_vertex("K10", 9, 9).is_err());
+        assert_eq!(vertex_text(Move::Play(3, 15), 19), "Q16");
+
+        // On a board wider than it is tall the numbers stop at the height
+        assert_eq!(parse_vertex("M3", 13, 5), Ok(Move::Play(2, 11)));
+        assert!(parse_vertex("A6", 13, 5).is_err());
+        assert_eq!(vertex_text(Move::Play(4, 12), 5), "N1");
+    }
+
+    #[test]
+    fn test_session() {
+        let responses = session(
+            "1 protocol_version\n\
+             # a comment line\n\
+             boardsize 5\n\
+             clear_board\n\
+             komi 0.5\n\
+             play b A5\n\
+             play w B5\n\
+             play b c3\n\
+             2 play w A4\n\
+             play b c3\n\
+             known_command genmove\n\
+             final_score\n\
+             undo\n\
+             undo\n\
+             undo\n\
+             undo\n\
+             undo\n\
+             frobnicate\n\
+             quit\n\
+             name\n"
+        );
+        assert_eq!(responses, vec![
+            "=1 2", "=", "=", "=", "=", "=", "=", "=2",
+            "? illegal move: That point is already occupied",
+            "= true", "= W+2.5", "=", "=", "=", "=", "? cannot undo", "? unknown command", "=",
+        ]);
+    }
+
+    #[test]
+    fn test_genmove_plays_legal_moves_until_passing() {
+        let mut commands = String::from("boardsize 7\n");
+        for _ in 0..200 {
+            commands.push_str("genmove b\ngenmove w\n");
+        }
+        commands.push_str("showboard\n");
+        let responses = session(&commands);
+        let moves = &responses[1..401];
+        assert!(moves.iter().all(|response| response.starts_with("= ")));
+        assert!(moves.iter().any(|response| response == "= pass"));
+        let board = responses[401].split_once('\n').unwrap().1;
+        assert!(board.starts_with("    A B C D E F G\n 7 "));
+        assert!(board.contains('X') && board.contains('O'));
+    }
+
+    #[test]
+    fn test_moves_after_passes() {
+        let responses = session(
+            "boardsize 5\n\
+             play b C3\n\
+             play w pass\n\
+             play b pass\n\
+             play w D4\n\
+             genmove b\n\
+             genmove w\n\
+             showboard\n"
+        );
+        // The stone played after the passes is on the engine's board
+        assert_eq!(responses[..5], ["=", "=", "=", "=", "="]);
+        assert!(responses[5].starts_with("= ") && responses[6].starts_with("= "));
+        assert!(responses[7].contains("\n 4  . . . O . 4\n 3  . . X . . 3\n"));
+    }
+
+    #[test]
+    fn test_rectangular_board() {
+        let responses = session(
+            "rectangular_boardsize 5 3\n\
+             play b E3\n\
+             play w E4\n\
+             rectangular_boardsize 5 26\n\
+             showboard\n"
+        );
+        assert_eq!(responses[..4], ["=", "=", "? illegal move: off the board", "? unacceptable size"]);
+        assert!(responses[4].contains("\n    A B C D E\n 3  . . . . X 3\n 2 "));
+    }
+}
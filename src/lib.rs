@@ -0,0 +1,16 @@
+//! Rules engine for the game of Go
+//!
+//! Board state, captures, ko and scoring live in `game` and `scoring`, game records in `tree` and `sgf`,
+//! the Go Text Protocol in `gtp` and `engine`, and board coordinate labels in `coordinates`. None of it depends on Bevy, the board window is
+//! the `gui` feature of the `go` binary.
+
+pub mod coordinates;
+pub mod engine;
+pub mod game;
+pub mod gtp;
+pub mod scoring;
+pub mod sgf;
+#[cfg(test)]
+mod test_rng;
+pub mod tree;
+pub mod zobrist;
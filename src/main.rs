@@ -1,12 +1,77 @@
-mod game;
+#[cfg(feature = "gui")]
 mod grid;
 
+use go::gtp;
+
+#[cfg(feature = "gui")]
 use bevy::prelude::*;
-use grid::GridPlugin;
+#[cfg(feature = "gui")]
+use grid::{GridPlugin, DEFAULT_BOARD_SIZE};
+#[cfg(feature = "gui")]
+use go::game::{MAX_BOARD_SIZE, MIN_BOARD_SIZE};
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_plugins(GridPlugin)
-        .run();
-}
\ No newline at end of file
+    // `go gtp` speaks GTP on stdin and stdout without opening a window
+    if std::env::args().nth(1).as_deref() == Some("gtp") {
+        if let Err(error) = gtp::run_stdio() {
+            eprintln!("GTP session ended: {error}");
+        }
+        return;
+    }
+
+    #[cfg(feature = "gui")]
+    {
+        // The board window logs every move to the terminal it was started from
+        go::game::set_move_log(true);
+        App::new()
+            .add_plugins(DefaultPlugins)
+            .add_plugins(GridPlugin {
+                engine_command: option_argument("engine"),
+                board_size: size_argument(),
+            })
+            .run();
+    }
+
+    #[cfg(not(feature = "gui"))]
+    eprintln!("Built without the gui feature, run `go gtp` for the GTP engine mode");
+}
+
+/**
+ * The value of `--<name> <value>` or `--<name>=<value>`, such as the GTP engine to play against
+ * from `--engine "<command line>"`
+ */
+#[cfg(feature = "gui")]
+fn option_argument(name: &str) -> Option<String> {
+    let flag = format!("--{name}");
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+        if let Some(value) = arg.strip_prefix(&format!("{flag}=")) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+/**
+ * Columns and rows of the board from `--size 19` or `--size 13x9`, 9x9 if not given
+ */
+#[cfg(feature = "gui")]
+fn size_argument() -> (usize, usize) {
+    let value = match option_argument("size") {
+        Some(value) => value,
+        None => return (DEFAULT_BOARD_SIZE, DEFAULT_BOARD_SIZE),
+    };
+    let side = |text: &str| text.trim().parse::<usize>().ok()
+        .filter(|side| (MIN_BOARD_SIZE..=MAX_BOARD_SIZE).contains(side));
+    let size = match value.split_once(['x', 'X']) {
+        Some((columns, rows)) => side(columns).zip(side(rows)),
+        None => side(&value).map(|side| (side, side)),
+    };
+    size.unwrap_or_else(|| {
+        eprintln!("Board size {value} is not supported, sides are {MIN_BOARD_SIZE} to {MAX_BOARD_SIZE}");
+        (DEFAULT_BOARD_SIZE, DEFAULT_BOARD_SIZE)
+    })
+}
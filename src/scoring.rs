@@ -0,0 +1,378 @@
+use crate::game::{
+    self, Board, ChainStatus, Color, GamePhase, GameResult, ScoringMethod, TerritoryOwner,
+};
+
+/**
+ * Breakdown of a scored position
+ * Territory counts empty points surrounded by only one color, including points of dead stones.
+ * Prisoners are the stones captured during play plus the dead stones removed at the end.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score {
+    pub method: ScoringMethod,
+    pub black_stones: u32,
+    pub white_stones: u32,
+    pub black_territory: u32,
+    pub white_territory: u32,
+    pub black_prisoners: u32,
+    pub white_prisoners: u32,
+    //Komi including any handicap compensation
+    pub komi: f32,
+}
+
+impl Score {
+    pub fn black_total(&self) -> f32 {
+        match self.method {
+            ScoringMethod::Area => (self.black_stones + self.black_territory) as f32,
+            ScoringMethod::Territory => (self.black_territory + self.black_prisoners) as f32,
+        }
+    }
+
+    pub fn white_total(&self) -> f32 {
+        let points = match self.method {
+            ScoringMethod::Area => self.white_stones + self.white_territory,
+            ScoringMethod::Territory => self.white_territory + self.white_prisoners,
+        };
+        points as f32 + self.komi
+    }
+
+    pub fn result(&self) -> GameResult {
+        let difference = self.black_total() - self.white_total();
+        if difference > 0.0 {
+            GameResult::Score { winner: Color::Black, margin: difference }
+        } else if difference < 0.0 {
+            GameResult::Score { winner: Color::White, margin: -difference }
+        } else {
+            GameResult::Draw
+        }
+    }
+}
+
+/**
+ * Area scoring (Chinese / Tromp-Taylor)
+ * Every living stone on the board plus every empty point that reaches only that color counts one point.
+ * White receives the komi plus any handicap compensation, see Board::white_compensation.
+ */
+pub fn score_area(board: &Board) -> Score {
+    count_score(board, ScoringMethod::Area)
+}
+
+/**
+ * Territory scoring (Japanese / Korean)
+ * Surrounded empty points plus prisoners, where prisoners include the stones marked dead.
+ * Points next to a chain in seki count for nobody.
+ * White receives the komi plus any handicap compensation, see Board::white_compensation.
+ */
+pub fn score_territory(board: &Board) -> Score {
+    count_score(board, ScoringMethod::Territory)
+}
+
+/**
+ * Score the board with the scoring method of its rule set
+ */
+pub fn score_board(board: &Board) -> Score {
+    match board.rules.scoring_method {
+        ScoringMethod::Area => score_area(board),
+        ScoringMethod::Territory => score_territory(board),
+    }
+}
+
+fn count_score(board: &Board, method: ScoringMethod) -> Score {
+    let territory = build_territory_map(board, method);
+    let mut score = Score {
+        method,
+        black_stones: 0,
+        white_stones: 0,
+        black_territory: 0,
+        white_territory: 0,
+        black_prisoners: board.black_captured,
+        white_prisoners: board.white_captured,
+        komi: board.white_compensation(),
+    };
+    for (row, points) in territory.iter().enumerate() {
+        for (col, owner) in points.iter().enumerate() {
+            match owner {
+                TerritoryOwner::Black => score.black_territory += 1,
+                TerritoryOwner::White => score.white_territory += 1,
+                TerritoryOwner::Neutral => {}
+            }
+            let dead = board.get_chain_status(row, col) == ChainStatus::Dead;
+            match (board.point_state(row, col).stone(), dead) {
+                (Some(Color::Black), false) => score.black_stones += 1,
+                (Some(Color::White), false) => score.white_stones += 1,
+                (Some(Color::Black), true) => score.white_prisoners += 1,
+                (Some(Color::White), true) => score.black_prisoners += 1,
+                (None, _) => {}
+            }
+        }
+    }
+    score
+}
+
+/**
+ * During the scoring phase, cycle the chain at (row,col) through alive, dead and seki
+ * Every stone of the chain gets the same status.
+ * Returns false if the game is not being scored or the point is empty
+ */
+pub fn toggle_chain_status(board: &mut Board, row: usize, col: usize) -> bool {
+    if board.phase != GamePhase::Scoring || board.point_state(row, col).is_empty() {
+        return false;
+    }
+    let next_status = match board.get_chain_status(row, col) {
+        ChainStatus::Alive => ChainStatus::Dead,
+        ChainStatus::Dead => ChainStatus::Seki,
+        ChainStatus::Seki => ChainStatus::Alive,
+    };
+    let chain = game::collect_chain(board, row, col);
+    for stone in chain {
+        if next_status == ChainStatus::Alive {
+            board.chain_status.remove(&stone);
+        } else {
+            board.chain_status.insert(stone, next_status);
+        }
+    }
+    true
+}
+
+/**
+ * Call once the players agree on dead stones and seki
+ * Scores the board, records the result and finishes the game.
+ * Returns None if the game is still being played or already finished
+ */
+pub fn score_game(board: &mut Board) -> Option<GameResult> {
+    if board.phase != GamePhase::Scoring {
+        return None;
+    }
+    let result = score_board(board).result();
+    board.result = Some(result);
+    board.phase = GamePhase::Finished;
+    Some(result)
+}
+
+/**
+ * Build a map of who owns each point of the board, using the rule set's scoring method
+ * Empty regions bordered by a single color are that player's territory, regions touching
+ * both colors (or none) and the points of living stones are neutral.
+ * Dead stones are treated as empty points of the region they sit in.
+ * The stones themselves stay on the board, the map only holds territory.
+ */
+pub fn territory_map(board: &Board) -> Vec<Vec<TerritoryOwner>> {
+    build_territory_map(board, board.rules.scoring_method)
+}
+
+fn build_territory_map(board: &Board, method: ScoringMethod) -> Vec<Vec<TerritoryOwner>> {
+    let living_stone = |row: usize, col: usize| -> Option<Color> {
+        if board.get_chain_status(row, col) == ChainStatus::Dead {
+            None
+        } else {
+            board.point_state(row, col).stone()
+        }
+    };
+    let mut map = vec![vec![TerritoryOwner::Neutral; board.width]; board.height];
+    let mut visited = vec![vec![false; board.width]; board.height];
+
+    for row in 0..board.height {
+        for col in 0..board.width {
+            if visited[row][col] || living_stone(row, col).is_some() {
+                continue;
+            }
+            // Flood fill the empty region and note which chains border it
+            let mut region: Vec<(usize, usize)> = Vec::new();
+            let mut to_visit: Vec<(usize, usize)> = vec![(row, col)];
+            let mut touches_black = false;
+            let mut touches_white = false;
+            let mut touches_seki = false;
+            visited[row][col] = true;
+            while let Some((visit_row, visit_col)) = to_visit.pop() {
+                region.push((visit_row, visit_col));
+                for (next_row, next_col) in board.adjacent_points(visit_row, visit_col) {
+                    let next_stone = living_stone(next_row, next_col);
+                    if next_stone.is_some() && board.get_chain_status(next_row, next_col) == ChainStatus::Seki {
+                        touches_seki = true;
+                    }
+                    match next_stone {
+                        Some(Color::Black) => touches_black = true,
+                        Some(Color::White) => touches_white = true,
+                        None => {
+                            if !visited[next_row][next_col] {
+                                visited[next_row][next_col] = true;
+                                to_visit.push((next_row, next_col));
+                            }
+                        }
+                    }
+                }
+            }
+
+            let owner = match (touches_black, touches_white) {
+                _ if touches_seki && method == ScoringMethod::Territory => TerritoryOwner::Neutral,
+                (true, false) => TerritoryOwner::Black,
+                (false, true) => TerritoryOwner::White,
+                _ => TerritoryOwner::Neutral,
+            };
+            for (region_row, region_col) in region {
+                map[region_row][region_col] = owner;
+            }
+        }
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{
+        self, Board, ChainStatus, Color, GamePhase, GameResult, PointState, ScoringMethod, TerritoryOwner,
+    };
+    use crate::scoring;
+
+    //Fill whole columns with a color, which splits the board into regions
+    fn board_with_walls(board_size: usize, walls: &[(usize, Color)]) -> Board {
+        let mut board = Board::new(board_size);
+        for &(col, color) in walls {
+            for row in 0..board_size {
+                board.update_board_color(row, col, color.into());
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn test_territory_map() {
+        let board = board_with_walls(5, &[(1, Color::Black), (3, Color::White)]);
+        let map = scoring::territory_map(&board);
+        // The walls themselves are stones, not territory
+        for row in map {
+            assert_eq!(row, vec![
+                TerritoryOwner::Black,
+                TerritoryOwner::Neutral,
+                TerritoryOwner::Neutral,
+                TerritoryOwner::Neutral,
+                TerritoryOwner::White,
+            ]);
+        }
+    }
+
+    #[test]
+    fn test_score_area_with_komi() {
+        let mut board = board_with_walls(5, &[(2, Color::Black), (3, Color::White)]);
+        board.rules.komi = 1.5;
+        let score = scoring::score_area(&board);
+        assert_eq!(score.black_stones, 5);
+        assert_eq!(score.black_territory, 10);
+        assert_eq!(score.white_stones, 5);
+        assert_eq!(score.white_territory, 5);
+        assert_eq!(score.black_total(), 15.0);
+        assert_eq!(score.white_total(), 11.5);
+        assert_eq!(score.result(), GameResult::Score { winner: Color::Black, margin: 3.5 });
+        assert_eq!(score.result().to_string(), "B+3.5");
+    }
+
+    #[test]
+    fn test_score_area_draw() {
+        let mut board = board_with_walls(5, &[(1, Color::Black), (3, Color::White)]);
+        board.rules.komi = 0.0;
+        assert_eq!(scoring::score_area(&board).result(), GameResult::Draw);
+        board.rules.komi = 7.5;
+        assert_eq!(scoring::score_area(&board).result().to_string(), "W+7.5");
+    }
+
+    #[test]
+    fn test_score_game_after_two_passes() {
+        let mut board = Board::new(9);
+        assert_eq!(scoring::score_game(&mut board), None);
+
+        game::pass(&mut board);
+        game::pass(&mut board);
+        let result = scoring::score_game(&mut board);
+
+        // An empty board is all neutral, so white wins by komi
+        assert_eq!(result, Some(GameResult::Score { winner: Color::White, margin: 7.5 }));
+        assert_eq!(board.result, result);
+        assert_eq!(board.phase, GamePhase::Finished);
+    }
+
+    #[test]
+    fn test_score_area_handicap_compensation() {
+        let mut board = board_with_walls(5, &[(1, Color::Black), (3, Color::White)]);
+        board.rules = game::RuleSet::chinese();
+        board.rules.komi = 7.5;
+        board.handicap = 3;
+
+        // Chinese rules give white a point for each handicap stone instead of the even-game komi
+        let score = scoring::score_board(&board);
+        assert_eq!(score.komi, 3.5);
+        assert_eq!(score.result().to_string(), "W+3.5");
+    }
+
+    #[test]
+    fn test_score_territory_counts_prisoners() {
+        let mut board = board_with_walls(5, &[(2, Color::Black), (3, Color::White)]);
+        board.rules.komi = 6.5;
+        board.black_captured = 2;
+        board.white_captured = 4;
+        let score = scoring::score_territory(&board);
+
+        // Stones on the board are not counted, prisoners are
+        assert_eq!(score.black_total(), 12.0);
+        assert_eq!(score.white_total(), 15.5);
+        assert_eq!(score.result().to_string(), "W+3.5");
+    }
+
+    #[test]
+    fn test_dead_stones_become_territory_and_prisoners() {
+        let mut board = board_with_walls(5, &[(1, Color::Black), (3, Color::White)]);
+        board.rules.scoring_method = ScoringMethod::Territory;
+        board.rules.komi = 0.0;
+        board.update_board_color(2, 4, PointState::Stone(Color::Black));
+        game::pass(&mut board);
+        game::pass(&mut board);
+
+        // While alive the black stone spoils white's territory on the right edge
+        assert_eq!(scoring::score_board(&board).white_territory, 0);
+
+        assert!(scoring::toggle_chain_status(&mut board, 2, 4));
+        assert_eq!(board.get_chain_status(2, 4), ChainStatus::Dead);
+        let score = scoring::score_board(&board);
+        assert_eq!(score.white_territory, 5);
+        assert_eq!(score.white_prisoners, 1);
+        assert_eq!(scoring::territory_map(&board)[2][4], TerritoryOwner::White);
+
+        // Area scoring does not care about prisoners, only the freed point
+        assert_eq!(scoring::score_area(&board).white_total(), 10.0);
+    }
+
+    #[test]
+    fn test_toggle_chain_status_cycles_whole_chain() {
+        let mut board = board_with_walls(5, &[(2, Color::Black)]);
+        assert!(!scoring::toggle_chain_status(&mut board, 0, 2));
+        game::pass(&mut board);
+        game::pass(&mut board);
+        assert!(!scoring::toggle_chain_status(&mut board, 0, 0));
+
+        assert!(scoring::toggle_chain_status(&mut board, 0, 2));
+        assert!((0..5).all(|row| board.get_chain_status(row, 2) == ChainStatus::Dead));
+        assert!(scoring::toggle_chain_status(&mut board, 4, 2));
+        assert!((0..5).all(|row| board.get_chain_status(row, 2) == ChainStatus::Seki));
+        assert!(scoring::toggle_chain_status(&mut board, 2, 2));
+        assert!(board.chain_status.is_empty());
+    }
+
+    #[test]
+    fn test_seki_points_count_for_nobody() {
+        let mut board = board_with_walls(5, &[(1, Color::Black), (2, Color::White)]);
+        board.rules.scoring_method = ScoringMethod::Territory;
+        game::pass(&mut board);
+        game::pass(&mut board);
+        scoring::toggle_chain_status(&mut board, 0, 1);
+        scoring::toggle_chain_status(&mut board, 0, 1);
+        assert_eq!(board.get_chain_status(0, 1), ChainStatus::Seki);
+
+        // Points next to the chain in seki are neutral under territory scoring
+        let map = scoring::territory_map(&board);
+        assert!((0..5).all(|row| map[row][0] == TerritoryOwner::Neutral && map[row][1] == TerritoryOwner::Neutral));
+        assert_eq!(scoring::score_territory(&board).black_territory, 0);
+        assert_eq!(scoring::score_territory(&board).white_territory, 10);
+
+        // Area scoring still counts them for the owner
+        assert_eq!(scoring::score_area(&board).black_territory, 5);
+    }
+}
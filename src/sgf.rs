@@ -0,0 +1,740 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::game::{Color, GamePhase, GameResult, GameState, KoRule, Move, MoveError, PointState, RuleSet, MAX_BOARD_SIZE, MIN_BOARD_SIZE};
+use crate::tree::{GameTree, Node, ROOT};
+
+//SGF (FF[4]) game records
+//A point is written as two letters, column then row, so (row 0, col 2) is "ca"
+//and "aa" is the top left corner of the board.
+
+/**
+ * Write the whole game tree as an SGF record
+ * The root holds the game information and the starting stones, the main line comes first
+ * and every other variation follows in brackets at the move where it branches off.
+ * RE is written once the game at the end of the main line has a result, wherever the tree is.
+ * A resignation has no move of its own, RE[B+R] or RE[W+R] records it.
+ */
+pub fn export(tree: &GameTree) -> String {
+    let start = tree.start().board();
+    let mut sgf = String::from("(;FF[4]GM[1]CA[UTF-8]AP[rust-go]");
+    if start.is_square() {
+        sgf.push_str(&format!("SZ[{}]", start.width));
+    } else {
+        sgf.push_str(&format!("SZ[{}:{}]", start.width, start.height));
+    }
+    sgf.push_str(&format!("KM[{}]", start.komi()));
+    sgf.push_str(&format!("RU[{}]", rules_name(&start.rules)));
+    if start.handicap > 0 {
+        sgf.push_str(&format!("HA[{}]", start.handicap));
+    }
+
+    // Handicap stones are part of the starting position rather than a node
+    let stones: Vec<(usize, usize, PointState)> = start.stones().into_iter()
+        .map(|(row, col, color)| (row, col, color.into()))
+        .collect();
+    write_setup(&stones, &mut sgf);
+    if start.is_white_turn {
+        sgf.push_str("PL[W]");
+    }
+    // Every node in the tree was played on the way in, so the main line replays
+    if let Some(result) = tree.replay(tree.main_line_end()).ok().and_then(|state| state.board().result) {
+        sgf.push_str(&format!("RE[{}]", result_text(&result)));
+    }
+    write_properties(tree.node(ROOT), &mut sgf);
+    sgf.push('\n');
+
+    write_line(tree, ROOT, &mut sgf);
+    sgf.push_str(")\n");
+    sgf
+}
+
+/**
+ * Two letter SGF point for (row, col)
+ */
+pub fn point_text(row: usize, col: usize) -> String {
+    format!("{}{}", point_letter(col), point_letter(row))
+}
+
+/**
+ * The SGF letter for a row or column, a for the first
+ */
+pub fn point_letter(index: usize) -> char {
+    (b'a' + index as u8) as char
+}
+
+/**
+ * The RU value for a rule set, using the names FF[4] defines where there is one
+ */
+pub fn rules_name(rules: &RuleSet) -> &'static str {
+    match rules.name {
+        "New Zealand" => "NZ",
+        name => name,
+    }
+}
+
+//SGF writes a draw as 0 rather than the word shown on screen
+fn result_text(result: &GameResult) -> String {
+    match result {
+        GameResult::Draw => "0".to_string(),
+        result => result.to_string(),
+    }
+}
+
+//Nodes with a single child follow on in the same sequence, a branch puts each child in brackets
+//A resignation node with nothing else in it is left out, RE records it
+fn write_line(tree: &GameTree, node_id: usize, sgf: &mut String) {
+    let mut node_id = node_id;
+    loop {
+        let children: Vec<usize> = tree.node(node_id).children.iter()
+            .copied()
+            .filter(|&child| !is_bare_resignation(tree.node(child)))
+            .collect();
+        match children.len() {
+            0 => return,
+            1 => {
+                node_id = children[0];
+                write_node(tree.node(node_id), sgf);
+            }
+            _ => {
+                for child in children {
+                    sgf.push_str("\n(");
+                    write_node(tree.node(child), sgf);
+                    write_line(tree, child, sgf);
+                    sgf.push(')');
+                }
+                return;
+            }
+        }
+    }
+}
+
+fn is_bare_resignation(node: &Node) -> bool {
+    matches!(node.played, Some((_, Move::Resign))) && node.setup.is_empty() && node.comment.is_empty()
+}
+
+fn write_node(node: &Node, sgf: &mut String) {
+    sgf.push(';');
+    // A resignation has no SGF move, RE records it instead
+    if let Some((player_color, played)) = node.played {
+        let property = player_color.letter();
+        match played {
+            Move::Play(row, col) => sgf.push_str(&format!("{}[{}]", property, point_text(row, col))),
+            Move::Pass => sgf.push_str(&format!("{}[]", property)),
+            Move::Resign => {}
+        }
+    }
+    write_setup(&node.setup, sgf);
+    write_properties(node, sgf);
+}
+
+fn write_properties(node: &Node, sgf: &mut String) {
+    if !node.comment.is_empty() {
+        sgf.push_str(&format!("C[{}]", escape_text(&node.comment)));
+    }
+}
+
+//AB, AW and AE each list their points after a single property name
+fn write_setup(stones: &[(usize, usize, PointState)], sgf: &mut String) {
+    let properties = [
+        ("AB", PointState::Stone(Color::Black)),
+        ("AW", PointState::Stone(Color::White)),
+        ("AE", PointState::Empty),
+    ];
+    for (property, state) in properties {
+        let points: Vec<String> = stones.iter()
+            .filter(|stone| stone.2 == state)
+            .map(|&(row, col, _)| format!("[{}]", point_text(row, col)))
+            .collect();
+        if !points.is_empty() {
+            sgf.push_str(property);
+            sgf.push_str(&points.concat());
+        }
+    }
+}
+
+//Text values end at ']', so it and the escape character itself need a backslash
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        if character == ']' || character == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
+
+/**
+ * Reason a game record could not be read
+ * Syntax errors give the byte offset in the text, an illegal move gives its move number
+ * counted from the start of the game along its own variation
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum SgfError {
+    Syntax { offset: usize, expected: &'static str },
+    NoGame,
+    BoardSize(String),
+    InvalidPoint { property: String, value: String },
+    InvalidValue { property: String, value: String },
+    IllegalMove { move_number: usize, error: MoveError },
+}
+
+impl fmt::Display for SgfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SgfError::Syntax { offset, expected } => write!(f, "SGF syntax error at byte {offset}: expected {expected}"),
+            SgfError::NoGame => write!(f, "There is no game in the file"),
+            SgfError::BoardSize(size) => write!(f, "Board size {size} is not supported"),
+            SgfError::InvalidPoint { property, value } => write!(f, "{property}[{value}] is not a point on the board"),
+            SgfError::InvalidValue { property, value } => write!(f, "{property}[{value}] is not a valid value"),
+            SgfError::IllegalMove { move_number, error } => write!(f, "Move {move_number} is illegal: {error}"),
+        }
+    }
+}
+
+/**
+ * Read every game in an SGF collection and replay it
+ * The root node sets the board size, rules, komi, handicap and starting stones. Every move in
+ * every variation is then played through the rules engine and the first illegal one is
+ * reported with its move number. Properties the game does not use are skipped.
+ */
+pub fn import(text: &str) -> Result<Vec<GameTree>, SgfError> {
+    let (nodes, roots) = Parser::new(text).collection()?;
+    if roots.is_empty() {
+        return Err(SgfError::NoGame);
+    }
+    roots.iter().map(|&root| build_game(&nodes, root)).collect()
+}
+
+//A node as read from the file, before any of its properties are understood
+struct RawNode {
+    properties: Vec<(String, Vec<String>)>,
+    children: Vec<usize>,
+}
+
+impl RawNode {
+    //First value of the property, if the node has it
+    fn value(&self, name: &str) -> Option<&str> {
+        self.properties.iter()
+            .find(|(property, _)| property == name)
+            .map(|(_, values)| values[0].as_str())
+    }
+}
+
+//Reads the tree structure without recursion, so deeply nested variations cannot overflow the stack
+struct Parser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Parser {
+            chars: text.char_indices().peekable(),
+            end: text.len(),
+        }
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.end, |&(offset, _)| offset)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, character)| character.is_whitespace()).is_some() {}
+    }
+
+    //All nodes in the file and the root node of each game
+    fn collection(&mut self) -> Result<(Vec<RawNode>, Vec<usize>), SgfError> {
+        let mut nodes: Vec<RawNode> = Vec::new();
+        let mut roots = Vec::new();
+        // For each open bracket, the node its first node follows on from
+        let mut open: Vec<Option<usize>> = Vec::new();
+        let mut last = None;
+        while let Some((offset, character)) = self.chars.next() {
+            match character {
+                '(' => {
+                    open.push(last);
+                    self.skip_whitespace();
+                    if self.chars.peek().map(|&(_, next)| next) != Some(';') {
+                        return Err(SgfError::Syntax { offset: self.offset(), expected: "';' to start a sequence" });
+                    }
+                }
+                // Anything outside a game, such as a mail header, is ignored
+                _ if open.is_empty() => {}
+                ')' => last = open.pop().flatten(),
+                ';' => {
+                    let node = nodes.len();
+                    nodes.push(RawNode {
+                        properties: self.properties()?,
+                        children: Vec::new(),
+                    });
+                    match last {
+                        Some(parent) => nodes[parent].children.push(node),
+                        None => roots.push(node),
+                    }
+                    last = Some(node);
+                }
+                _ if character.is_whitespace() => {}
+                _ => return Err(SgfError::Syntax { offset, expected: "a node or a variation" }),
+            }
+        }
+        if !open.is_empty() {
+            return Err(SgfError::Syntax { offset: self.end, expected: "')' to close the game" });
+        }
+        Ok((nodes, roots))
+    }
+
+    //Lower case letters in property names come from FF[3] long names like AddBlack and are dropped
+    fn properties(&mut self) -> Result<Vec<(String, Vec<String>)>, SgfError> {
+        let mut properties = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if !self.chars.peek().is_some_and(|(_, character)| character.is_ascii_alphabetic()) {
+                return Ok(properties);
+            }
+            let mut name = String::new();
+            while let Some((_, character)) = self.chars.next_if(|(_, character)| character.is_ascii_alphabetic()) {
+                if character.is_ascii_uppercase() {
+                    name.push(character);
+                }
+            }
+            let mut values = Vec::new();
+            loop {
+                self.skip_whitespace();
+                if self.chars.next_if(|&(_, character)| character == '[').is_none() {
+                    break;
+                }
+                values.push(self.value()?);
+            }
+            if values.is_empty() {
+                return Err(SgfError::Syntax { offset: self.offset(), expected: "'[' after a property name" });
+            }
+            properties.push((name, values));
+        }
+    }
+
+    //A backslash keeps the next character, except that an escaped line break is a soft break and is removed
+    fn value(&mut self) -> Result<String, SgfError> {
+        let mut value = String::new();
+        while let Some((_, character)) = self.chars.next() {
+            match character {
+                ']' => return Ok(value),
+                '\\' => match self.chars.next() {
+                    Some((_, '\n')) => {
+                        self.chars.next_if(|&(_, next)| next == '\r');
+                    }
+                    Some((_, '\r')) => {
+                        self.chars.next_if(|&(_, next)| next == '\n');
+                    }
+                    Some((_, escaped)) => value.push(escaped),
+                    None => break,
+                },
+                _ => value.push(character),
+            }
+        }
+        Err(SgfError::Syntax { offset: self.end, expected: "']' to close a value" })
+    }
+}
+
+//Root properties set up the starting position, then the variations are played depth first
+fn build_game(nodes: &[RawNode], root: usize) -> Result<GameTree, SgfError> {
+    let root_node = &nodes[root];
+    let size = match root_node.value("SZ") {
+        Some(value) => parse_size(value)?,
+        None => (19, 19),
+    };
+    let mut rules = root_node.value("RU").and_then(rules_from_name).unwrap_or_else(unknown_rules);
+    if let Some(value) = root_node.value("KM") {
+        rules.komi = value.trim().parse().map_err(|_| invalid_value("KM", value))?;
+    }
+    let handicap = match root_node.value("HA") {
+        Some(value) => value.trim().parse::<usize>().map_err(|_| invalid_value("HA", value))?,
+        None => 0,
+    };
+
+    // Black stones at the root of a handicap game are placed as handicap, so white's
+    // compensation and the first turn follow from them
+    let mut start = GameState::with_dimensions(size.0, size.1, rules);
+    let setup = read_setup(root_node, size)?;
+    let black_stones: Vec<(usize, usize)> = setup.iter()
+        .filter(|stone| stone.2 == PointState::Stone(Color::Black))
+        .map(|&(row, col, _)| (row, col))
+        .collect();
+    let placed = handicap >= 2 && black_stones.len() == setup.len() && start.place_recorded_handicap(&black_stones);
+    if !placed && !setup.is_empty() {
+        start.setup(&setup);
+    }
+    if let Some(value) = root_node.value("PL") {
+        start.set_to_move(parse_color("PL", value)?);
+    }
+    let mut tree = GameTree::new(start);
+    tree.set_comment(root_node.value("C").unwrap_or_default());
+
+    // Each node is entered with the number of moves before it and left by stepping back
+    enum Step {
+        Enter(usize, usize),
+        Leave,
+    }
+    let mut steps: Vec<Step> = root_node.children.iter().rev().map(|&child| Step::Enter(child, 0)).collect();
+    while let Some(step) = steps.pop() {
+        match step {
+            Step::Enter(node_id, moves_before) => {
+                let node = read_node(&nodes[node_id], size)?;
+                let move_number = moves_before + usize::from(node.played.is_some());
+                if let Err(error) = tree.add_node(node) {
+                    return Err(SgfError::IllegalMove { move_number, error });
+                }
+                steps.push(Step::Leave);
+                steps.extend(nodes[node_id].children.iter().rev().map(|&child| Step::Enter(child, move_number)));
+            }
+            Step::Leave => {
+                if let Err(error) = tree.back() {
+                    return Err(SgfError::IllegalMove { move_number: tree.move_number(), error });
+                }
+            }
+        }
+    }
+
+    // The resignation is put back as the last move of the main line
+    if let Some(winner) = root_node.value("RE").and_then(resignation_winner) {
+        let replayed = tree.go_to(tree.main_line_end())
+            .and_then(|()| match tree.state().board().phase {
+                GamePhase::Playing => {
+                    tree.add_node(Node::new(Some((winner.opponent(), Move::Resign)), Vec::new(), String::new())).map(|_| ())
+                }
+                _ => Ok(()),
+            });
+        if let Err(error) = replayed {
+            return Err(SgfError::IllegalMove { move_number: tree.move_number() + 1, error });
+        }
+        if let Err(error) = tree.go_to(ROOT) {
+            return Err(SgfError::IllegalMove { move_number: 0, error });
+        }
+    }
+    Ok(tree)
+}
+
+//The winner of a game RE records as won by resignation, B+R or W+Resign
+fn resignation_winner(value: &str) -> Option<Color> {
+    let (winner, reason) = value.trim().split_once('+')?;
+    if !matches!(reason.to_ascii_lowercase().as_str(), "r" | "resign") {
+        return None;
+    }
+    match winner {
+        "B" => Some(Color::Black),
+        "W" => Some(Color::White),
+        _ => None,
+    }
+}
+
+fn read_node(raw: &RawNode, size: (usize, usize)) -> Result<Node, SgfError> {
+    let played = match (raw.value("B"), raw.value("W")) {
+        (Some(_), Some(value)) => return Err(invalid_value("W", value)),
+        (Some(value), None) => Some((Color::Black, parse_move("B", value, size)?)),
+        (None, Some(value)) => Some((Color::White, parse_move("W", value, size)?)),
+        (None, None) => None,
+    };
+    Ok(Node::new(played, read_setup(raw, size)?, raw.value("C").unwrap_or_default().to_string()))
+}
+
+//AB, AW and AE values are single points or compressed lists like aa:cc, a rectangle given by two corners
+fn read_setup(raw: &RawNode, size: (usize, usize)) -> Result<Vec<(usize, usize, PointState)>, SgfError> {
+    let mut stones = Vec::new();
+    for (property, values) in &raw.properties {
+        let state = match property.as_str() {
+            "AB" => PointState::Stone(Color::Black),
+            "AW" => PointState::Stone(Color::White),
+            "AE" => PointState::Empty,
+            _ => continue,
+        };
+        for value in values {
+            let (first, second) = match value.split_once(':') {
+                Some((first, second)) => (parse_point(property, first, size)?, parse_point(property, second, size)?),
+                None => {
+                    let point = parse_point(property, value, size)?;
+                    (point, point)
+                }
+            };
+            for row in first.0.min(second.0)..=first.0.max(second.0) {
+                for col in first.1.min(second.1)..=first.1.max(second.1) {
+                    stones.push((row, col, state));
+                }
+            }
+        }
+    }
+    Ok(stones)
+}
+
+//An empty value is a pass, and so is tt on boards up to 19x19 as in FF[3]
+fn parse_move(property: &str, value: &str, size: (usize, usize)) -> Result<Move, SgfError> {
+    if value.is_empty() || (value == "tt" && size.0 <= 19 && size.1 <= 19) {
+        return Ok(Move::Pass);
+    }
+    let (row, col) = parse_point(property, value, size)?;
+    Ok(Move::Play(row, col))
+}
+
+//Letters a-z are 0-25 and A-Z 26-51, column first, size is (columns, rows)
+fn parse_point(property: &str, value: &str, size: (usize, usize)) -> Result<(usize, usize), SgfError> {
+    let index = |letter: char| match letter {
+        'a'..='z' => Some(letter as usize - 'a' as usize),
+        'A'..='Z' => Some(letter as usize - 'A' as usize + 26),
+        _ => None,
+    };
+    let letters: Vec<char> = value.chars().collect();
+    match letters.as_slice() {
+        &[col, row] => match (index(row), index(col)) {
+            (Some(row), Some(col)) if col < size.0 && row < size.1 => Ok((row, col)),
+            _ => Err(SgfError::InvalidPoint { property: property.to_string(), value: value.to_string() }),
+        },
+        _ => Err(SgfError::InvalidPoint { property: property.to_string(), value: value.to_string() }),
+    }
+}
+
+//SZ[size] for a square board or SZ[columns:rows] for a rectangular one, returned as (columns, rows)
+fn parse_size(value: &str) -> Result<(usize, usize), SgfError> {
+    let side = |text: &str| text.trim().parse::<usize>().ok()
+        .filter(|side| (MIN_BOARD_SIZE..=MAX_BOARD_SIZE).contains(side));
+    let size = match value.split_once(':') {
+        Some((columns, rows)) => side(columns).zip(side(rows)),
+        None => side(value).map(|side| (side, side)),
+    };
+    size.ok_or_else(|| SgfError::BoardSize(value.to_string()))
+}
+
+fn parse_color(property: &str, value: &str) -> Result<Color, SgfError> {
+    match value.trim() {
+        "B" | "b" => Ok(Color::Black),
+        "W" | "w" => Ok(Color::White),
+        _ => Err(invalid_value(property, value)),
+    }
+}
+
+//Matches the RU names written by export and the common spellings used by other programs,
+//anything else is played under the default rules
+fn rules_from_name(name: &str) -> Option<RuleSet> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "aga" => Some(RuleSet::aga()),
+        "chinese" | "cn" => Some(RuleSet::chinese()),
+        "japanese" | "jp" | "korean" => Some(RuleSet::japanese()),
+        "nz" | "new zealand" => Some(RuleSet::new_zealand()),
+        "tromp-taylor" | "tromp taylor" | "tt" => Some(RuleSet::tromp_taylor()),
+        _ => None,
+    }
+}
+
+//Without known rules a record is replayed under the default rules with simple ko,
+//so a move is not refused for a superko the game may not have been played with
+fn unknown_rules() -> RuleSet {
+    RuleSet {
+        name: "Unknown",
+        ko_rule: KoRule::Simple,
+        ..RuleSet::default()
+    }
+}
+
+fn invalid_value(property: &str, value: &str) -> SgfError {
+    SgfError::InvalidValue { property: property.to_string(), value: value.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Color, GamePhase, GameResult, GameState, KoRule, Move, MoveError, PointState, RuleSet};
+    use crate::sgf::{self, point_text, SgfError};
+    use crate::test_rng::TestRng;
+    use crate::tree::GameTree;
+
+    #[test]
+    fn test_point_text_is_column_then_row() {
+        assert_eq!(point_text(0, 0), "aa");
+        assert_eq!(point_text(0, 2), "ca");
+        assert_eq!(point_text(3, 15), "pd");
+        assert_eq!(point_text(18, 18), "ss");
+    }
+
+    #[test]
+    fn test_export_game_with_variation() {
+        let mut tree = GameTree::new(GameState::with_rules(9, RuleSet::japanese()));
+        tree.play(Move::Play(2, 2)).unwrap();
+        tree.set_comment("Komoku [3-3]");
+        tree.play(Move::Play(6, 6)).unwrap();
+        tree.play(Move::Pass).unwrap();
+        assert_eq!(tree.back(), Ok(true));
+        assert_eq!(tree.back(), Ok(true));
+        tree.play(Move::Play(2, 6)).unwrap();
+        assert_eq!(
+            sgf::export(&tree),
+            "(;FF[4]GM[1]CA[UTF-8]AP[rust-go]SZ[9]KM[6.5]RU[Japanese]\n\
+             ;B[cc]C[Komoku [3-3\\]]\n\
+             (;W[gg];B[])\n\
+             (;W[gc]))\n"
+        );
+    }
+
+    #[test]
+    fn test_export_handicap_and_result() {
+        let mut state = GameState::with_rules(9, RuleSet::new_zealand());
+        state.place_fixed_handicap(2);
+        let mut tree = GameTree::new(state);
+        tree.play(Move::Play(4, 4)).unwrap();
+        tree.play(Move::Resign).unwrap();
+        assert_eq!(
+            sgf::export(&tree),
+            "(;FF[4]GM[1]CA[UTF-8]AP[rust-go]SZ[9]KM[0.5]RU[NZ]HA[2]AB[gc][cg]PL[W]RE[W+R]\n\
+             ;W[ee])\n"
+        );
+    }
+
+    #[test]
+    fn test_resigned_game_round_trip() {
+        let mut tree = GameTree::new(GameState::new(9));
+        tree.play(Move::Play(2, 2)).unwrap();
+        tree.play(Move::Resign).unwrap();
+
+        // The result comes from the end of the main line wherever the tree is
+        assert_eq!(tree.back(), Ok(true));
+        assert_eq!(tree.back(), Ok(true));
+        let exported = sgf::export(&tree);
+        assert!(exported.contains("RE[B+R]"));
+        assert!(exported.ends_with(";B[cc])\n"));
+
+        let mut imported = sgf::import(&exported).unwrap().remove(0);
+        assert_eq!(sgf::export(&imported), exported);
+        assert_eq!(imported.go_to(imported.main_line_end()), Ok(()));
+        assert_eq!(imported.state().board().result, Some(GameResult::Resignation { winner: Color::Black }));
+        assert_eq!(imported.move_number(), 2);
+    }
+
+    #[test]
+    fn test_import_round_trip() {
+        let mut tree = GameTree::new(GameState::with_rules(9, RuleSet::aga()));
+        tree.play(Move::Play(2, 2)).unwrap();
+        tree.set_comment("Line one\nand \\ two]");
+        tree.play(Move::Play(6, 6)).unwrap();
+        tree.play(Move::Pass).unwrap();
+        assert_eq!(tree.back(), Ok(true));
+        tree.play(Move::Play(6, 2)).unwrap();
+        let exported = sgf::export(&tree);
+
+        let games = sgf::import(&exported).unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(sgf::export(&games[0]), exported);
+        assert_eq!(games[0].start().board().rules, RuleSet::aga());
+        let mut imported = games[0].clone();
+        assert_eq!(imported.forward(), Ok(true));
+        assert_eq!(imported.node(imported.current()).comment, "Line one\nand \\ two]");
+    }
+
+    #[test]
+    fn test_import_setup_escapes_and_handicap() {
+        let games = sgf::import(
+            "junk before (;GM[1]FF[4]SZ[9]KM[0.5]RU[Japanese]HA[2]AB[cg][gc]PlayerBlack[Ann]\n\
+             ;W[ee]C[soft\\\nbreak \\\\ and \\] kept];AddBlack[aa:bc]AE[bc]C[setup])"
+        ).unwrap();
+        let mut tree = games[0].clone();
+        let board = tree.start().board();
+        assert_eq!(board.handicap, 2);
+        assert_eq!(board.rules.komi, 0.5);
+        assert_eq!(board.point_state(6, 2), PointState::Stone(Color::Black));
+        assert!(board.is_white_turn);
+
+        assert_eq!(tree.forward(), Ok(true));
+        assert_eq!(tree.node(tree.current()).comment, "softbreak \\ and ] kept");
+        assert_eq!(tree.forward(), Ok(true));
+        let board = tree.state().board();
+        for (row, col) in [(0, 0), (0, 1), (1, 0), (1, 1), (2, 0)] {
+            assert_eq!(board.point_state(row, col), PointState::Stone(Color::Black));
+        }
+        assert!(board.point_state(2, 1).is_empty());
+        assert_eq!(board.point_state(4, 4), PointState::Stone(Color::White));
+    }
+
+    #[test]
+    fn test_import_reports_illegal_move_number() {
+        let result = sgf::import("(;SZ[9];B[aa];W[bb](;B[cc])(;B[bb]))");
+        assert_eq!(result.err(), Some(SgfError::IllegalMove { move_number: 3, error: MoveError::Occupied }));
+        assert_eq!(
+            sgf::import("(;SZ[9];B[aj])").err(),
+            Some(SgfError::InvalidPoint { property: "B".to_string(), value: "aj".to_string() })
+        );
+        assert_eq!(sgf::import("(;SZ[30])").err(), Some(SgfError::BoardSize("30".to_string())));
+        assert_eq!(sgf::import("no game here").err(), Some(SgfError::NoGame));
+        assert!(matches!(sgf::import("(;B[aa]"), Err(SgfError::Syntax { .. })));
+    }
+
+    #[test]
+    fn test_rectangular_board_round_trip() {
+        let mut tree = sgf::import("(;SZ[13:5];B[ma];W[ae])").unwrap().remove(0);
+        while tree.forward() == Ok(true) {}
+        let board = tree.state().board();
+        assert_eq!((board.width, board.height), (13, 5));
+        assert_eq!(board.point_state(0, 12), PointState::Stone(Color::Black));
+        assert_eq!(board.point_state(4, 0), PointState::Stone(Color::White));
+        assert!(sgf::export(&tree).starts_with("(;FF[4]GM[1]CA[UTF-8]AP[rust-go]SZ[13:5]"));
+
+        // Points past the short side are off the board
+        assert_eq!(
+            sgf::import("(;SZ[13:5];B[af])").err(),
+            Some(SgfError::InvalidPoint { property: "B".to_string(), value: "af".to_string() })
+        );
+        assert_eq!(sgf::import("(;SZ[5:1])").err(), Some(SgfError::BoardSize("5:1".to_string())));
+    }
+
+    #[test]
+    fn test_import_moves_after_passes_and_unknown_rules() {
+        // Play carries on after both players passed, as records of disputed dead stones do
+        let mut tree = sgf::import("(;SZ[9];B[cc];W[];B[];W[gg];B[dd])").unwrap().remove(0);
+        assert_eq!(tree.go_to(tree.main_line_end()), Ok(()));
+        assert_eq!(tree.move_number(), 5);
+        assert_eq!(tree.state().board().phase, GamePhase::Playing);
+        assert_eq!(tree.state().board().stones().len(), 3);
+        assert_eq!(tree.state().to_move(), Color::White);
+
+        // Without known rules the ko rule is the one that refuses the fewest moves
+        let rules = tree.start().board().rules;
+        assert_eq!((rules.name, rules.ko_rule), ("Unknown", KoRule::Simple));
+        let unknown = sgf::import("(;SZ[9]RU[Ing])").unwrap().remove(0);
+        assert_eq!(unknown.start().board().rules.ko_rule, KoRule::Simple);
+        let chinese = sgf::import("(;SZ[9]RU[Chinese])").unwrap().remove(0);
+        assert_eq!(chinese.start().board().rules, RuleSet::chinese());
+    }
+
+    #[test]
+    fn test_import_survives_mangled_records() {
+        let record = "(;FF[4]SZ[9]KM[7.5]HA[2]AB[cg][gc]C[root\\]];W[ee]\n(;B[dd];W[]C[x])(;B[ff]AE[cg]AW[aa:bb]))";
+        let alphabet: Vec<char> = "();[]\\:ABWCEHKMPLSZaeirtw09 .\n".chars().collect();
+        let mut rng = TestRng::new(12345);
+        let mut next = |bound: usize| rng.below(bound);
+        for _ in 0..5000 {
+            // Mutate a real record by deleting, inserting and replacing characters
+            let mut text: Vec<char> = record.chars().collect();
+            for _ in 0..1 + next(6) {
+                let position = next(text.len() + 1);
+                match next(3) {
+                    0 if position < text.len() => {
+                        text.remove(position);
+                    }
+                    1 => text.insert(position, alphabet[next(alphabet.len())]),
+                    _ if position < text.len() => text[position] = alphabet[next(alphabet.len())],
+                    _ => {}
+                }
+            }
+            let text: String = text.into_iter().collect();
+            if let Ok(games) = sgf::import(&text) {
+                for game in &games {
+                    sgf::export(game);
+                }
+            }
+
+            // And random text from the same characters
+            let random: String = (0..next(60)).map(|_| alphabet[next(alphabet.len())]).collect();
+            let _ = sgf::import(&random);
+        }
+
+        // Deep nesting is read without recursion
+        let nested = "(;".repeat(100_000) + &")".repeat(100_000);
+        assert_eq!(sgf::import(&nested).unwrap().len(), 1);
+    }
+}
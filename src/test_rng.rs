@@ -0,0 +1,18 @@
+//Deterministic pseudo-random numbers for the tests that play long random games or mangle game records
+//A 64 bit linear congruential generator, so the same seed always gives the same sequence
+
+pub(crate) struct TestRng(u64);
+
+impl TestRng {
+    pub fn new(seed: u64) -> Self {
+        TestRng(seed)
+    }
+
+    /**
+     * A number from 0 up to but not including bound
+     */
+    pub fn below(&mut self, bound: usize) -> usize {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize % bound
+    }
+}
@@ -0,0 +1,460 @@
+use crate::game::{Color, GameState, Move, MoveError, MoveOutcome, PointState};
+
+/**
+ * Index of the root node, the starting position of the game
+ */
+pub const ROOT: usize = 0;
+
+/**
+ * One position in the game tree
+ * played is None for the root and for nodes that only add setup stones.
+ * The first child continues the main line, any later children are variations.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Node {
+    pub played: Option<(Color, Move)>,
+    pub setup: Vec<(usize, usize, PointState)>,
+    pub comment: String,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    //The child forward() follows, the one most recently visited
+    selected: usize,
+}
+
+impl Node {
+    /**
+     * A node that is not linked into a tree yet, see GameTree::add_node
+     */
+    pub fn new(played: Option<(Color, Move)>, setup: Vec<(usize, usize, PointState)>, comment: String) -> Self {
+        Node {
+            played,
+            setup,
+            comment,
+            ..Node::default()
+        }
+    }
+}
+
+/**
+ * A game with its variations
+ * Nodes are stored by index and link to their parent and children.
+ * The tree keeps the GameState of the current node, moving to another node replays its
+ * moves from the start, or takes back a single move when stepping back.
+ */
+#[derive(Clone)]
+pub struct GameTree {
+    start: GameState,
+    nodes: Vec<Node>,
+    current: usize,
+    state: GameState,
+}
+
+impl GameTree {
+    /**
+     * A tree with only a root node, holding the given starting position
+     */
+    pub fn new(start: GameState) -> Self {
+        GameTree {
+            state: start.clone(),
+            start,
+            nodes: vec![Node::default()],
+            current: ROOT,
+        }
+    }
+
+    /**
+     * The position at the root, before any node's setup stones or moves
+     */
+    pub fn start(&self) -> &GameState {
+        &self.start
+    }
+
+    /**
+     * The game at the current node
+     */
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn node(&self, node_id: usize) -> &Node {
+        &self.nodes[node_id]
+    }
+
+    /**
+     * Play a move from the current node
+     * If the move is already a child of the current node the tree moves there,
+     * otherwise it is added as a new variation after any existing ones.
+     */
+    pub fn play(&mut self, next_move: Move) -> Result<MoveOutcome, MoveError> {
+        let played = Some((self.state.to_move(), next_move));
+        let outcome = self.state.play(next_move)?;
+        let existing = self.nodes[self.current].children.iter()
+            .position(|&child| self.nodes[child].played == played && self.nodes[child].setup.is_empty());
+        let index = match existing {
+            Some(index) => index,
+            None => {
+                let child = self.nodes.len();
+                self.nodes.push(Node {
+                    played,
+                    parent: Some(self.current),
+                    ..Node::default()
+                });
+                self.nodes[self.current].children.push(child);
+                self.nodes[self.current].children.len() - 1
+            }
+        };
+        let parent = &mut self.nodes[self.current];
+        parent.selected = index;
+        self.current = parent.children[index];
+        Ok(outcome)
+    }
+
+    /**
+     * Add a node below the current one and move to it, placing its setup stones and playing its move
+     * Used to build a tree from a game record, so the move is played by the color in the node
+     * even if it is not that player's turn. An illegal move adds nothing.
+     */
+    pub fn add_node(&mut self, node: Node) -> Result<MoveOutcome, MoveError> {
+        match apply_node(&mut self.state, &node) {
+            Ok(outcome) => {
+                let child = self.nodes.len();
+                self.nodes.push(Node {
+                    parent: Some(self.current),
+                    children: Vec::new(),
+                    selected: 0,
+                    ..node
+                });
+                self.nodes[self.current].children.push(child);
+                self.current = child;
+                Ok(outcome)
+            }
+            Err(error) => {
+                // The setup stones may already be down
+                self.restore();
+                Err(error)
+            }
+        }
+    }
+
+    /**
+     * Step back to the parent node
+     * Returns Ok(false) at the root, or the error of a node that cannot be replayed with the tree left in place
+     */
+    pub fn back(&mut self) -> Result<bool, MoveError> {
+        let node = &self.nodes[self.current];
+        let parent = match node.parent {
+            Some(parent) => parent,
+            None => return Ok(false),
+        };
+        // An empty node changed nothing and a plain move can be taken back,
+        // setup stones need the position replayed
+        let unchanged = node.played.is_none() && node.setup.is_empty();
+        let undone = node.played.is_some() && node.setup.is_empty() && self.state.undo();
+        if !unchanged && !undone {
+            self.state = self.replay(parent)?;
+        }
+        self.current = parent;
+        Ok(true)
+    }
+
+    /**
+     * Step forward to the child last visited, the main line if none has been
+     * Returns Ok(false) at the end of a line, or the error of a child that cannot be played here
+     * with the tree left in place
+     */
+    pub fn forward(&mut self) -> Result<bool, MoveError> {
+        let node = &self.nodes[self.current];
+        let child = match node.children.get(node.selected) {
+            Some(&child) => child,
+            None => return Ok(false),
+        };
+        if let Err(error) = apply_node(&mut self.state, &self.nodes[child]) {
+            self.restore();
+            return Err(error);
+        }
+        self.current = child;
+        Ok(true)
+    }
+
+    /**
+     * Switch to the next sibling of the current node
+     */
+    pub fn next_variation(&mut self) -> Result<bool, MoveError> {
+        self.switch_variation(true)
+    }
+
+    /**
+     * Switch to the previous sibling of the current node
+     */
+    pub fn previous_variation(&mut self) -> Result<bool, MoveError> {
+        self.switch_variation(false)
+    }
+
+    fn switch_variation(&mut self, next: bool) -> Result<bool, MoveError> {
+        let (index, count) = self.variation(self.current);
+        let target = if next { index + 1 } else { index.wrapping_sub(1) };
+        if target >= count || !self.back()? {
+            return Ok(false);
+        }
+        self.nodes[self.current].selected = target;
+        self.forward().or_else(|error| {
+            // Stay on the variation we came from if the other one cannot be played
+            self.nodes[self.current].selected = index;
+            self.forward()?;
+            Err(error)
+        })
+    }
+
+    /**
+     * Move straight to any node, replaying the game from the start
+     * Every node on the way is remembered as the one forward() follows.
+     * Returns the error of the first node on the way that cannot be played, the tree stays put then
+     */
+    pub fn go_to(&mut self, node_id: usize) -> Result<(), MoveError> {
+        self.state = self.replay(node_id)?;
+        let mut child = node_id;
+        while let Some(parent) = self.nodes[child].parent {
+            let index = self.variation(child).0;
+            self.nodes[parent].selected = index;
+            child = parent;
+        }
+        self.current = node_id;
+        Ok(())
+    }
+
+    /**
+     * The game at node_id, built from the start position
+     * Stops with the error of the first node on the way that cannot be played
+     */
+    pub fn replay(&self, node_id: usize) -> Result<GameState, MoveError> {
+        let mut state = self.start.clone();
+        for node in self.path(node_id) {
+            apply_node(&mut state, &self.nodes[node])?;
+        }
+        Ok(state)
+    }
+
+    //Rebuild the game at the current node after a node failed part way through, its setup stones may be down
+    fn restore(&mut self) {
+        if let Ok(state) = self.replay(self.current) {
+            self.state = state;
+        }
+    }
+
+    /**
+     * Every node from the root down to node_id
+     */
+    pub fn path(&self, node_id: usize) -> Vec<usize> {
+        let mut path = vec![node_id];
+        while let Some(parent) = self.nodes[*path.last().unwrap()].parent {
+            path.push(parent);
+        }
+        path.reverse();
+        path
+    }
+
+    /**
+     * Position of node_id among its siblings and how many there are, (0, 1) for the root
+     */
+    pub fn variation(&self, node_id: usize) -> (usize, usize) {
+        match self.nodes[node_id].parent {
+            Some(parent) => {
+                let siblings = &self.nodes[parent].children;
+                (siblings.iter().position(|&child| child == node_id).unwrap_or(0), siblings.len())
+            }
+            None => (0, 1),
+        }
+    }
+
+    /**
+     * The last node of the main line, reached by taking the first child from the root each time
+     */
+    pub fn main_line_end(&self) -> usize {
+        let mut node_id = ROOT;
+        while let Some(&child) = self.nodes[node_id].children.first() {
+            node_id = child;
+        }
+        node_id
+    }
+
+    /**
+     * Whether node_id is on the main line, reached by taking the first child from the root each time
+     */
+    pub fn is_main_line(&self, node_id: usize) -> bool {
+        self.path(node_id).iter().all(|&node| self.variation(node).0 == 0)
+    }
+
+    /**
+     * Number of moves played from the root to the current node, passes included
+     */
+    pub fn move_number(&self) -> usize {
+        self.path(self.current).iter()
+            .filter(|&&node| self.nodes[node].played.is_some())
+            .count()
+    }
+
+    pub fn set_comment(&mut self, comment: &str) {
+        self.nodes[self.current].comment = comment.to_string();
+    }
+
+    /**
+     * During scoring, cycle the chain at (row,col) through alive, dead and seki
+     */
+    pub fn toggle_chain_status(&mut self, row: usize, col: usize) -> bool {
+        self.state.toggle_chain_status(row, col)
+    }
+
+    /**
+     * Accept the marked dead stones and seki, then score and finish the game
+     */
+    pub fn finish_scoring(&mut self) {
+        self.state.finish_scoring();
+    }
+}
+
+//Setup stones go down first, then the move is played by the color recorded in the node
+//Records may carry on after both players passed, so play resumes instead of refusing the move
+fn apply_node(state: &mut GameState, node: &Node) -> Result<MoveOutcome, MoveError> {
+    if !node.setup.is_empty() {
+        state.setup(&node.setup);
+    }
+    match node.played {
+        Some((player_color, next_move)) => {
+            state.set_to_move(player_color);
+            state.play_on(next_move)
+        }
+        None => Ok(MoveOutcome::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Color, GameState, Move, MoveError, PointState};
+    use crate::tree::{GameTree, Node, ROOT};
+
+    fn play_all(tree: &mut GameTree, moves: &[(usize, usize)]) {
+        for &(row, col) in moves {
+            tree.play(Move::Play(row, col)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_branch_from_earlier_position() {
+        let mut tree = GameTree::new(GameState::new(9));
+        play_all(&mut tree, &[(2, 2), (6, 6), (2, 6)]);
+        let main_end = tree.current();
+        let main_hash = tree.state().board().hash;
+
+        // Go back two moves and play something else for white
+        assert_eq!(tree.back(), Ok(true));
+        assert_eq!(tree.back(), Ok(true));
+        assert!(tree.state().board().point_state(6, 6).is_empty());
+        assert_eq!(tree.state().to_move(), Color::White);
+        play_all(&mut tree, &[(4, 4)]);
+        let variation = tree.current();
+        assert_eq!(tree.variation(variation), (1, 2));
+        assert!(!tree.is_main_line(variation));
+        assert!(tree.is_main_line(main_end));
+        assert_eq!(tree.move_number(), 2);
+
+        // Forward from the branch point follows the line visited last
+        assert_eq!(tree.back(), Ok(true));
+        assert_eq!(tree.forward(), Ok(true));
+        assert_eq!(tree.current(), variation);
+
+        // Switching variations goes back to the main line
+        assert_eq!(tree.previous_variation(), Ok(true));
+        assert_eq!(tree.previous_variation(), Ok(false));
+        assert_eq!(tree.forward(), Ok(true));
+        assert_eq!(tree.current(), main_end);
+        assert_eq!(tree.state().board().hash, main_hash);
+        assert_eq!(tree.forward(), Ok(false));
+    }
+
+    #[test]
+    fn test_replaying_existing_move_reuses_node() {
+        let mut tree = GameTree::new(GameState::new(9));
+        play_all(&mut tree, &[(2, 2), (6, 6)]);
+        let node_count = tree.path(tree.current()).len();
+        assert_eq!(tree.back(), Ok(true));
+        play_all(&mut tree, &[(6, 6)]);
+        assert_eq!(tree.path(tree.current()).len(), node_count);
+        assert_eq!(tree.node(tree.node(tree.current()).parent.unwrap()).children.len(), 1);
+
+        // An illegal move adds nothing
+        assert!(tree.play(Move::Play(6, 6)).is_err());
+        assert!(tree.node(tree.current()).children.is_empty());
+    }
+
+    #[test]
+    fn test_unplayable_node_leaves_tree_in_place() {
+        let mut tree = GameTree::new(GameState::new(9));
+        play_all(&mut tree, &[(2, 2)]);
+        let played = tree.current();
+        let hash = tree.state().board().hash;
+
+        // Setup stones go down before the move to an occupied point fails
+        let broken = tree.nodes.len();
+        tree.nodes.push(Node {
+            setup: vec![(5, 5, Color::Black.into())],
+            played: Some((Color::White, Move::Play(2, 2))),
+            parent: Some(played),
+            ..Node::default()
+        });
+        tree.nodes[played].children.push(broken);
+
+        assert_eq!(tree.forward(), Err(MoveError::Occupied));
+        assert_eq!(tree.go_to(broken), Err(MoveError::Occupied));
+        assert_eq!(tree.current(), played);
+        assert_eq!(tree.state().board().hash, hash);
+        assert!(tree.state().board().point_state(5, 5).is_empty());
+        assert_eq!(tree.state().to_move(), Color::White);
+
+        // The position is still the one at the cursor, so play carries on from it
+        play_all(&mut tree, &[(6, 6)]);
+        assert_eq!(tree.move_number(), 2);
+        let variation = tree.current();
+
+        // Switching to the broken variation stays on this one
+        assert_eq!(tree.previous_variation(), Err(MoveError::Occupied));
+        assert_eq!(tree.current(), variation);
+        assert_eq!(tree.state().board().point_state(6, 6), PointState::Stone(Color::White));
+        assert_eq!(tree.back(), Ok(true));
+        assert_eq!(tree.state().board().hash, hash);
+    }
+
+    #[test]
+    fn test_go_to_replays_setup_and_captures() {
+        let mut tree = GameTree::new(GameState::new(9));
+        let setup_node = tree.nodes.len();
+        tree.nodes.push(Node {
+            setup: vec![(0, 0, Color::Black.into()), (0, 1, Color::White.into())],
+            parent: Some(ROOT),
+            comment: "White to capture".to_string(),
+            ..Node::default()
+        });
+        tree.nodes[ROOT].children.push(setup_node);
+        assert_eq!(tree.go_to(setup_node), Ok(()));
+        tree.state.set_to_move(Color::White);
+        play_all(&mut tree, &[(1, 0)]);
+        let capture = tree.current();
+        assert!(tree.state().board().point_state(0, 0).is_empty());
+
+        // Back over the setup node replays from the start
+        assert_eq!(tree.back(), Ok(true));
+        assert_eq!(tree.state().board().point_state(0, 0), PointState::Stone(Color::Black));
+        assert_eq!(tree.back(), Ok(true));
+        assert!(tree.state().board().point_state(0, 0).is_empty());
+        assert_eq!(tree.back(), Ok(false));
+
+        assert_eq!(tree.go_to(capture), Ok(()));
+        assert!(tree.state().board().point_state(0, 0).is_empty());
+        assert_eq!(tree.state().board().white_captured, 1);
+        assert_eq!(tree.node(setup_node).comment, "White to capture");
+        assert_eq!(tree.move_number(), 1);
+    }
+}
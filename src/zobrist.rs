@@ -0,0 +1,104 @@
+use std::sync::OnceLock;
+
+use crate::game::{Color, PointState};
+
+/**
+ * Largest board the key table covers, 25x25
+ */
+pub const MAX_POINTS: usize = 25 * 25;
+
+//Fixed seed so hashes are the same in every run and can be stored
+const SEED: u64 = 0x676f_5f7a_6f62_7269;
+
+/**
+ * Random 64-bit keys for Zobrist hashing
+ * A position's hash is the XOR of the key of every stone on the board,
+ * the side-to-move key when white is to move and the key of the ko point, if any.
+ * Points are indexed row by row, see Board::point_index
+ */
+pub struct ZobristTable {
+    black_stones: Vec<u64>,
+    white_stones: Vec<u64>,
+    ko_points: Vec<u64>,
+    white_to_move: u64,
+}
+
+impl ZobristTable {
+    fn new() -> Self {
+        let mut state = SEED;
+        let mut keys = |count: usize| -> Vec<u64> { (0..count).map(|_| splitmix64(&mut state)).collect() };
+        let black_stones = keys(MAX_POINTS);
+        let white_stones = keys(MAX_POINTS);
+        let ko_points = keys(MAX_POINTS);
+        let white_to_move = keys(1)[0];
+        ZobristTable {
+            black_stones,
+            white_stones,
+            ko_points,
+            white_to_move,
+        }
+    }
+
+    /**
+     * Key for what is on the point at point_index, 0 for an empty point
+     */
+    pub fn stone(&self, point_index: usize, state: PointState) -> u64 {
+        match state {
+            PointState::Stone(Color::White) => self.white_stones[point_index],
+            PointState::Stone(Color::Black) => self.black_stones[point_index],
+            PointState::Empty => 0,
+        }
+    }
+
+    pub fn ko_point(&self, point_index: usize) -> u64 {
+        self.ko_points[point_index]
+    }
+
+    pub fn white_to_move(&self) -> u64 {
+        self.white_to_move
+    }
+}
+
+/**
+ * The shared key table, built on first use
+ */
+pub fn table() -> &'static ZobristTable {
+    static TABLE: OnceLock<ZobristTable> = OnceLock::new();
+    TABLE.get_or_init(ZobristTable::new)
+}
+
+//SplitMix64, small and good enough for hashing keys
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::game::{Color, PointState};
+    use crate::zobrist::{self, MAX_POINTS};
+
+    #[test]
+    fn test_keys_are_distinct_and_fixed() {
+        let table = zobrist::table();
+        let mut keys = HashSet::new();
+        for point_index in 0..MAX_POINTS {
+            assert!(keys.insert(table.stone(point_index, Color::Black.into())));
+            assert!(keys.insert(table.stone(point_index, Color::White.into())));
+            assert!(keys.insert(table.ko_point(point_index)));
+        }
+        assert!(keys.insert(table.white_to_move()));
+        assert!(!keys.contains(&0));
+        assert_eq!(table.stone(0, PointState::Empty), 0);
+
+        // A second table built from the seed has the same keys
+        let rebuilt = zobrist::ZobristTable::new();
+        assert_eq!(rebuilt.stone(100, Color::White.into()), table.stone(100, Color::White.into()));
+        assert_eq!(rebuilt.white_to_move(), table.white_to_move());
+    }
+}
@@ -0,0 +1,26 @@
+#!/bin/sh
+# Stand-in GTP engine for the engine tests in src/engine.rs
+# The first argument is what genmove answers, or one of
+#   slow  - genmove never answers in time
+#   crash - the engine exits on genmove
+# showboard lists the vertices played since clear_board
+
+reply="${1:-pass}"
+played=""
+while read -r command arguments; do
+    case "$command" in
+        protocol_version) printf '= 2\n\n' ;;
+        boardsize|rectangular_boardsize|komi) printf '=\n\n' ;;
+        clear_board) played=""; printf '=\n\n' ;;
+        play) played="$played ${arguments#* }"; printf '=\n\n' ;;
+        showboard) printf '=%s\n\n' "$played" ;;
+        genmove)
+            case "$reply" in
+                slow) sleep 5 ;;
+                crash) exit 1 ;;
+                *) printf '= %s\n\n' "$reply" ;;
+            esac ;;
+        quit) printf '=\n\n'; exit 0 ;;
+        *) printf '? unknown command\n\n' ;;
+    esac
+done